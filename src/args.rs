@@ -0,0 +1,125 @@
+/**
+ * startup arguments.
+ * natively these come from the command line (`--seed 1234` or `--seed=1234`),
+ * in the wasm build from the url query (`index.html?seed=1234`).
+ * 
+ * --seed <n>       the seed of the run
+ * --record <path>  write every action to a recording file
+ * --replay <path>  play back a recording, then hand control to the player
+ * --level <name>   play a built-in level by name, or a level file
+ * --headless       no window: play back the replay (if any) and print the resulting board
+ */
+
+use rltk::console;
+
+#[derive(Default)]
+pub struct Args {
+    pub seed: Option<u64>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub level: Option<String>,
+    pub headless: bool,
+}
+
+impl Args {
+
+    /// a value that is given but unusable is an error, rather than quietly playing some other run
+    pub fn parse() -> Result<Self, String> {
+        Self::from_pairs(raw_pairs())
+    }
+
+    fn from_pairs(pairs: Vec<(String, String)>) -> Result<Self, String> {
+        let mut args = Args::default();
+        for (key, value) in pairs {
+            match key.as_str() {
+                "seed" => args.seed = Some(value.parse().map_err(|_| format!("--seed: '{}' is not a number", value))?),
+                "record" => args.record = Some(path(&key, value)?),
+                "replay" => args.replay = Some(path(&key, value)?),
+                "level" => args.level = Some(path(&key, value)?),
+                "headless" => args.headless = true,
+                _ => console::log(&format!("unknown argument: {}", key)),
+            }
+        }
+        Ok(args)
+    }
+}
+
+fn path(key: &str, value: String) -> Result<String, String> {
+    match value.is_empty() {
+        true => Err(format!("--{} needs a value", key)),
+        false => Ok(value),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn raw_pairs() -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut iter = std::env::args().skip(1).peekable();
+    while let Some(arg) = iter.next() {
+        let arg = arg.trim_start_matches('-').to_string();
+        if let Some((key, value)) = arg.split_once('=') {
+            pairs.push((key.to_string(), value.to_string()));
+            continue;
+        }
+        // the next item is our value, unless it is a flag itself
+        let value = match iter.peek() {
+            Some(next) if !next.starts_with("--") => iter.next().unwrap(),
+            _ => String::new(),
+        };
+        pairs.push((arg, value));
+    }
+    pairs
+}
+
+#[cfg(target_arch = "wasm32")]
+mod js {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        // defined in wasm/index.html
+        pub fn url_query() -> String;
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn raw_pairs() -> Vec<(String, String)> {
+    js::url_query()
+        .trim_start_matches('?')
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (pair.to_string(), String::new()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(pairs: &[(&str, &str)]) -> Result<Args, String> {
+        Args::from_pairs(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+
+    #[test]
+    fn good_arguments() {
+        let args = parse(&[("seed", "1234"), ("record", "run.txt"), ("headless", "")]).unwrap();
+        assert_eq!(args.seed, Some(1234));
+        assert_eq!(args.record.as_deref(), Some("run.txt"));
+        assert!(args.headless);
+    }
+
+    #[test]
+    fn a_bad_seed_is_refused() {
+        assert_eq!(parse(&[("seed", "12a4")]).err().unwrap(), "--seed: '12a4' is not a number");
+        assert!(parse(&[("seed", "")]).is_err());
+    }
+
+    #[test]
+    fn a_path_needs_a_value() {
+        assert_eq!(parse(&[("record", "")]).err().unwrap(), "--record needs a value");
+        assert!(parse(&[("replay", "")]).is_err());
+    }
+}
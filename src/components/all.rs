@@ -1,47 +1,110 @@
 use specs::prelude::*;
 use specs_derive::Component;
+use serde::{Serialize, Deserialize};
 use crate::{geo::Point, util::Dir};
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Direction {
     pub dir: Dir,
 }
 
-#[derive(Component)]
-pub struct Monster {}
-
-
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum ViewShedKind {
-    Radial,
-    Cone,
-    Line,
+    Radial, // all around
+    Cone, // `arc` radians wide, centered on the Direction
+    Line, // straight ahead
 }
 
-
-#[derive(Component)]
+// what an entity can see. Walls block the view, but are seen themselves
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Viewshed {
-    kind: ViewShedKind,
-    visible: Vec<usize>,
-    radius: f32,
-    arc: f32,
-    dirty: bool,
+    pub kind: ViewShedKind,
+    #[serde(skip)]
+    pub visible: Vec<usize>, // map indices
+    pub radius: f32,
+    pub arc: f32,
+    pub dirty: bool, // moved, turned, or the walls moved. recompute next time
 }
 
+impl Viewshed {
+
+    pub fn new(kind: ViewShedKind, radius: f32, arc: f32) -> Self {
+        Self { kind, visible: Vec::new(), radius, arc, dirty: true }
+    }
+
+    pub fn can_see(&self, index: usize) -> bool {
+        self.visible.contains(&index)
+    }
+}
 
-#[derive(Component)]
+
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ProjectileKind {
+    Shot, // pushes the first wall it hits, and is gone
+    Bouncing { bounces: i32 }, // bounces off the first wall, pushes the next one, and bounces off walls it cannot push
+}
+
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Projectile {
+    pub kind: ProjectileKind,
     pub dir: Dir,
     pub lifetime: i32,
+    pub power: i32, // damage dealt to whatever it hits
+    pub knockback: bool, // shove whatever it hits one cell further
+    pub strength: usize, // the longest row of walls and entities it can push
+    #[serde(skip)]
+    pub owner: Option<Entity>, // never hits whoever shot it, not even after a bounce. Saved as SavedEntity::shot_by
 }
 
+// reaching this cell finishes the level
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Exit {}
+
 // has a solid presence on the board
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Solid {}
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct CombatStats {
     pub max_hp : i32,
     pub hp : i32,
     pub defense : i32,
     pub power : i32
+}
+
+impl CombatStats {
+
+    /// how hard we hit someone with these stats
+    pub fn damage_to(&self, target: &CombatStats) -> i32 {
+        i32::max(0, self.power - target.defense)
+    }
+}
+
+// cannot be hurt for a couple of turns, like right after a respawn
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Invulnerable {
+    pub turns: i32,
+}
+
+// bumped into something, and wants to hit it this turn
+#[derive(Component, Debug, Clone)]
+pub struct WantsToMelee {
+    pub target: Entity,
+}
+
+// all damage dealt to an entity this turn, resolved by the damage system
+#[derive(Component, Debug)]
+pub struct SufferDamage {
+    pub amount: Vec<i32>,
+}
+
+impl SufferDamage {
+
+    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+        if let Some(suffering) = store.get_mut(victim) {
+            suffering.amount.push(amount);
+        } else {
+            store.insert(victim, SufferDamage { amount: vec![amount] }).expect("unable to insert damage");
+        }
+    }
 }
\ No newline at end of file
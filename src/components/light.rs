@@ -1 +1,36 @@
-// TODO allow things to light up the map
\ No newline at end of file
+use specs::prelude::*;
+use specs_derive::Component;
+use serde::{Serialize, Deserialize};
+
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum LightShape {
+    Omni, // all around
+    Cone { arc: f32 }, // `arc` radians wide, along the Direction of the entity, like a flashlight
+}
+
+// lights up the map around it. The light of all sources adds up
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct LightSource {
+    pub shape: LightShape,
+    pub radius: f32,
+    pub intensity: f32, // brightness at the source, 1 is full
+    pub color: (f32, f32, f32),
+    pub flicker: Option<f32>, // how much the intensity wavers from turn to turn, like a torch
+}
+
+impl LightSource {
+
+    pub fn new(radius: f32, intensity: f32, color: (f32, f32, f32)) -> Self {
+        Self { shape: LightShape::Omni, radius, intensity, color, flicker: None }
+    }
+
+    pub fn cone(mut self, arc: f32) -> Self {
+        self.shape = LightShape::Cone { arc };
+        self
+    }
+
+    pub fn flickering(mut self, amount: f32) -> Self {
+        self.flicker = Some(amount);
+        self
+    }
+}
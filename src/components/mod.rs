@@ -2,10 +2,14 @@ mod light;
 mod player;
 mod position;
 mod renderable;
+mod power;
+mod monster;
 mod all;
 
 pub use light::*;
 pub use player::*;
 pub use position::*;
 pub use renderable::*;
+pub use power::*;
+pub use monster::*;
 pub use all::*;
\ No newline at end of file
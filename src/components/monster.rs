@@ -0,0 +1,63 @@
+use specs::prelude::*;
+use specs_derive::Component;
+use serde::{Serialize, Deserialize};
+
+use crate::util::Dir;
+
+/// what a kind of monster does, besides walking into players. Anything left out of a template is off
+#[derive(PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Behavior {
+    pub asleep: bool, // starts out idle, and only wakes up once it spots a player. Cleared for good then
+    pub patrols: bool, // walks along the walls while there is nothing else to do
+    pub hunts: bool, // finds its way to players, and searches where it lost them. Otherwise just turns towards them
+    pub shoots: bool, // fires at players in line
+    pub pushes: bool, // shoves walls at players in line
+    pub flees_at: i32, // runs from players once its hp drops this low
+}
+
+/// the states of the monster AI. What the viewshed sees moves it from one to the other
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum AiState {
+    Idle, // stands still, or sleeps
+    Patrol, // walks along the walls
+    Alert, // just spotted a player, and takes a turn to react
+    Chase, // goes after a player, or where it was seen last
+    Flee, // hurt, and running away from players
+}
+
+#[derive(Component, Clone, Serialize, Deserialize)]
+pub struct Monster {
+    pub behavior: Behavior,
+    pub state: AiState,
+    pub unseen: i32, // turns since it last spotted a player
+}
+
+impl Monster {
+
+    pub fn new(behavior: Behavior) -> Self {
+        let mut monster = Self { behavior, state: AiState::Idle, unseen: 0 };
+        monster.state = monster.resting();
+        monster
+    }
+
+    /// the state to fall back to when there is nothing going on
+    pub fn resting(&self) -> AiState {
+        match self.behavior.patrols && !self.behavior.asleep {
+            true => AiState::Patrol,
+            false => AiState::Idle,
+        }
+    }
+}
+
+/// what a monster does next turn, seen from where it stands. Planned while the player thinks and shown on the map,
+/// then carried out in the monster turn, whatever the player did in the meantime
+#[derive(Component, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Intent {
+    Wait,
+    Turn(Dir),
+    Move(Dir),
+    Attack(Dir), // hits whoever stands in the next cell by then
+    Shoot(Dir),
+    Push(Dir), // shoves the wall in the next cell
+}
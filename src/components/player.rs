@@ -1,8 +1,12 @@
 use specs::prelude::*;
 use specs_derive::Component;
+use serde::{Serialize, Deserialize};
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Player {
 
 }
 
+// the body the player controls right now. Every other Player is a clone waiting for its turn
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Active {}
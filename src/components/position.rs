@@ -1,10 +1,11 @@
 use specs::prelude::*;
 use specs_derive::Component;
+use serde::{Serialize, Deserialize};
 
 use crate::geo::Point;
 
 // NOTE: its completely stupid why this is not a Point
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -0,0 +1,59 @@
+use specs::prelude::*;
+use specs_derive::Component;
+use serde::{Serialize, Deserialize};
+
+/// the special abilities, each used in the direction the player is facing
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Power {
+    Dash, // run up to a couple of free cells at once
+    Pull, // bring the first wall or entity in line right up to you
+    Swap, // trade places with the first entity in line
+    Freeze, // the first monster in line skips a couple of turns
+    Clone, // a second body in front of you, switch between them with tab
+    Bounce, // a shot that bounces off walls
+}
+
+impl Power {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Power::Dash => "dash",
+            Power::Pull => "pull",
+            Power::Swap => "swap",
+            Power::Freeze => "freeze",
+            Power::Clone => "clone",
+            Power::Bounce => "bounce",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Power> {
+        match name {
+            "dash" => Some(Power::Dash),
+            "pull" => Some(Power::Pull),
+            "swap" => Some(Power::Swap),
+            "freeze" => Some(Power::Freeze),
+            "clone" => Some(Power::Clone),
+            "bounce" => Some(Power::Bounce),
+            _ => None,
+        }
+    }
+}
+
+/// the powers someone holds. The first one is bound to key 1, the second to key 2, and so on
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Powers {
+    pub list: Vec<Power>,
+}
+
+impl Powers {
+
+    pub fn all() -> Self {
+        Self { list: vec![Power::Dash, Power::Pull, Power::Swap, Power::Freeze, Power::Clone, Power::Bounce] }
+    }
+}
+
+// frozen solid, and skips its turns until it thaws
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Frozen {
+    pub turns: i32,
+}
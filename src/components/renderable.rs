@@ -2,7 +2,7 @@ use rltk::RGB;
 use specs::prelude::*;
 use specs_derive::Component;
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct Renderable {
     pub glyph: rltk::FontCharType,
     pub foreground: RGB,
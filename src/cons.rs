@@ -7,7 +7,27 @@ pub const HEIGHT: usize = 23; // 41
 pub const HW: usize = WIDTH / 2;
 pub const HH: usize = HEIGHT / 2;
 
+pub const PUSH_STRENGTH: usize = 2; // how many walls or entities in a row a normal shot can move
+
+pub const REMEMBERED_BRIGHTNESS: f32 = 0.3; // tiles seen before, but not right now
+
+pub const POWER_RANGE: i32 = 8; // how far down the line pull, swap and freeze reach
+pub const DASH_DISTANCE: i32 = 4;
+pub const FREEZE_TURNS: i32 = 3;
+pub const FLEE_TURNS: i32 = 5; // how long hurt monsters keep running after losing sight of players
+pub const MAX_BODIES: usize = 3; // the player and their clones
+
+pub const BOUNCE_LIFETIME: i32 = 20; // bouncing shots need room to come back
+
+pub const CRUSH_DAMAGE: i32 = 1; // for being squeezed against a wall
+
+pub const INVULNERABLE_TURNS: i32 = 3; // after a respawn
+
+pub const RECORD_PATH: &str = "run.txt";
+
 pub const RGB_BACKGROUND: RGB = RGB { r: 0.0, g: 0.15, b: 0.25 };
+pub const RGB_DANGER: RGB = RGB { r: 0.55, g: 0.05, b: 0.05 }; // cells a monster is about to hit
+pub const RGB_SHOVE: RGB = RGB { r: 0.45, g: 0.25, b: 0.0 }; // walls a monster is about to push
 
 pub const HALF_PI: f32 = PI / 2.0;
 pub const PI: f32 = 3.14159265358979323846264338327950288f32;
@@ -74,12 +74,22 @@ impl Circle {
     /// - we do stupid things with the angles, not foolproof
     pub fn to_grid_arc(&self, from: f32, to: f32) -> Vec<Point> {
         let circle = self.to_grid_edge();
-        let arc = circle.into_iter().filter(|p| {
-            let angle = p.sub(&self.center).angle();
-            from < angle && angle <= to || 
-            from < angle+cons::TWO_PI && angle+cons::TWO_PI <= to ||
-            from < angle-cons::TWO_PI && angle-cons::TWO_PI <= to
-        }).collect();
+        let arc = circle.into_iter().filter(|p| self.in_arc(p, from, to)).collect();
         arc
     } 
+
+    /// is this point within the circle?
+    pub fn contains(&self, p: &Point) -> bool {
+        let d = p.sub(&self.center);
+        ((d.x * d.x + d.y * d.y) as f32) <= self.radius * self.radius
+    }
+
+    /// does this point lie between the two angles (in radians), as seen from the center?
+    /// same angles as `to_grid_arc`
+    pub fn in_arc(&self, p: &Point, from: f32, to: f32) -> bool {
+        let angle = p.sub(&self.center).angle();
+        from < angle && angle <= to || 
+        from < angle+cons::TWO_PI && angle+cons::TWO_PI <= to ||
+        from < angle-cons::TWO_PI && angle-cons::TWO_PI <= to
+    }
 }
@@ -1,8 +1,9 @@
 use specs::prelude::*;
 use specs_derive::Component;
+use serde::{Serialize, Deserialize};
 use crate::{cons, util::Dir};
 
-#[derive(Component, Debug, Clone, Copy)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
@@ -0,0 +1,238 @@
+/**
+ * drive the game without a window.
+ * Builds the same world as the real game, feeds it actions and lets the turn machine run,
+ * so runs can be simulated and inspected in tests or on a machine without a display.
+ */
+
+use specs::prelude::*;
+
+use crate::{
+    components::{Exit, Monster, Player, Position, Projectile},
+    map::{Map, Tile},
+    levels::Level,
+    raws::Raws,
+    recording::Recording,
+    state::{MyState, RunState},
+    systems::Action,
+};
+
+pub struct Simulation {
+    pub state: MyState,
+}
+
+impl Simulation {
+
+    pub fn new(seed: u64) -> Self {
+        let mut sim = Self { state: MyState::new_game(seed) };
+        sim.settle();
+        sim
+    }
+
+    pub fn with_level(seed: u64, level: &Level) -> Self {
+        let mut sim = Self { state: MyState::new_level(seed, level) };
+        sim.settle();
+        sim
+    }
+
+    /// a simulation of the run described by this recording
+    pub fn from_recording(recording: &Recording) -> Result<Self, String> {
+        let mut sim = match &recording.level {
+            Some(name) => Self::with_level(recording.seed, &Level::load(name)?),
+            None => Self::new(recording.seed),
+        };
+        recording.check_raws(&sim.state.ecs.fetch::<Raws>())?;
+        sim.run(&recording.actions);
+        Ok(sim)
+    }
+
+    /// play out one full turn: the player acts, then the monsters
+    pub fn step(&mut self, action: Action) {
+        self.state.advance(Some(action));
+        self.settle();
+    }
+
+    pub fn run(&mut self, actions: &[Action]) {
+        for action in actions {
+            self.step(*action);
+        }
+    }
+
+    /// advance the turn machine until it wants input again
+    fn settle(&mut self) {
+        while self.state.runstate.is_running() {
+            self.state.advance(None);
+        }
+    }
+
+    pub fn turns(&self) -> usize {
+        self.state.recording.actions.len()
+    }
+
+    pub fn map(&self) -> specs::shred::Fetch<'_, Map> {
+        self.state.ecs.fetch::<Map>()
+    }
+
+    pub fn player_positions(&self) -> Vec<(i32, i32)> {
+        self.positions_of::<Player>()
+    }
+
+    pub fn monster_positions(&self) -> Vec<(i32, i32)> {
+        self.positions_of::<Monster>()
+    }
+
+    pub fn projectile_positions(&self) -> Vec<(i32, i32)> {
+        self.positions_of::<Projectile>()
+    }
+
+    fn positions_of<T: Component>(&self) -> Vec<(i32, i32)> {
+        let positions = self.state.ecs.read_storage::<Position>();
+        let markers = self.state.ecs.read_storage::<T>();
+        (&positions, &markers).join().map(|(pos, _)| (pos.x, pos.y)).collect()
+    }
+
+    /// the board as plain text: `#` wall, `.` floor, ` ` abyss, `@` player, `M` monster, `*` projectile, `E` exit
+    pub fn to_text(&self) -> String {
+        let map = self.map();
+        let mut grid: Vec<char> = map.tiles.iter().map(|tile| match tile {
+            Tile::Wall => '#',
+            Tile::Floor => '.',
+            Tile::Empty => ' ',
+        }).collect();
+
+        let mut put = |points: Vec<(i32, i32)>, c: char| {
+            for (x, y) in points {
+                if let Some(id) = map.to_index(x, y) {
+                    grid[id] = c;
+                }
+            }
+        };
+        put(self.positions_of::<Exit>(), 'E');
+        put(self.projectile_positions(), '*');
+        put(self.monster_positions(), 'M');
+        put(self.player_positions(), '@');
+
+        grid.chunks(map.width).map(|row| row.iter().collect::<String>() + "\n").collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rltk::RandomNumberGenerator;
+    use crate::{components::Power, resources::Lives, util::Dir};
+
+    const ARENA: &str = "\
+#####
+#@<.#
+#####
+";
+
+    #[test]
+    fn bridge_is_crossed() {
+        let mut sim = Simulation::with_level(1, &Level::load("bridge").unwrap());
+        sim.run(&[Action::Move(Dir::Right), Action::Move(Dir::Right), Action::Shoot]);
+        sim.run(&[Action::Move(Dir::Right); 3]);
+
+        // the wall went into the abyss, and made a way across
+        assert_eq!(sim.to_text(), "\
+###########
+####   ####
+#......@E.#
+####   ####
+###########
+");
+        sim.step(Action::Move(Dir::Right));
+        assert_eq!(sim.player_positions(), vec![(8, 2)]);
+        assert!(sim.state.runstate == RunState::Victory { selection: 0 });
+    }
+
+    #[test]
+    fn same_seed_same_board() {
+        let actions = [Action::Move(Dir::Right), Action::Shoot, Action::Move(Dir::Down), Action::Move(Dir::Down)];
+        let mut a = Simulation::new(1234);
+        let mut b = Simulation::new(1234);
+        a.run(&actions);
+        b.run(&actions);
+        assert_eq!(a.to_text(), b.to_text());
+    }
+
+    #[test]
+    fn replay_gives_the_same_board() {
+        let mut sim = Simulation::new(99);
+        for dir in [Dir::Right, Dir::Right, Dir::Down, Dir::Left, Dir::Down, Dir::Right] {
+            sim.step(Action::Move(dir));
+        }
+        sim.step(Action::Shoot);
+
+        let recording = Recording::from_text(&sim.state.recording.to_text()).unwrap();
+        let replayed = Simulation::from_recording(&recording).unwrap();
+        assert_eq!(replayed.turns(), sim.turns());
+        assert_eq!(replayed.to_text(), sim.to_text());
+    }
+
+    #[test]
+    fn replays_need_the_same_templates() {
+        let mut sim = Simulation::new(5);
+        sim.step(Action::Move(Dir::Right));
+        let mut recording = Recording::from_text(&sim.state.recording.to_text()).unwrap();
+        assert_eq!(recording.raws, Some(sim.state.ecs.fetch::<Raws>().hash));
+
+        recording.raws = recording.raws.map(|hash| hash ^ 1);
+        match Simulation::from_recording(&recording) {
+            Ok(_) => panic!("replayed with other templates"),
+            Err(err) => assert!(err.starts_with("recorded with other entity templates"), "{}", err),
+        }
+        recording.raws = None; // from before templates were tracked
+        assert!(Simulation::from_recording(&recording).is_ok());
+    }
+
+    #[test]
+    fn saves_are_replayed() {
+        let mut sim = Simulation::new(7);
+        sim.run(&[Action::Move(Dir::Right), Action::Save, Action::Move(Dir::Down)]);
+        assert_eq!(sim.turns(), 3);
+
+        // the save reseeded the rng, and so does the replay of it
+        let recording = Recording::from_text(&sim.state.recording.to_text()).unwrap();
+        let replayed = Simulation::from_recording(&recording).unwrap();
+        assert_eq!(replayed.to_text(), sim.to_text());
+        let draw = |sim: &Simulation| sim.state.ecs.fetch_mut::<RandomNumberGenerator>().next_u64();
+        assert_eq!(draw(&replayed), draw(&sim));
+    }
+
+    #[test]
+    fn switching_bodies_shows_what_the_other_one_sees() {
+        let level = Level::parse("bend", "#######\n#@....#\n#####.#\n#.....#\n#######\n").unwrap();
+        let mut sim = Simulation::with_level(1, &level);
+        sim.run(&[Action::Move(Dir::Right), Action::Power(Power::Clone), Action::Switch]);
+        sim.run(&[Action::Move(Dir::Right), Action::Move(Dir::Right), Action::Move(Dir::Down), Action::Move(Dir::Down)]);
+        sim.run(&[Action::Move(Dir::Left); 4]);
+        assert_eq!(sim.player_positions(), vec![(2, 1), (1, 3)]);
+        assert!(!sim.state.ecs.fetch::<Map>().is_visible(2, 1), "around the bend, out of sight");
+
+        sim.step(Action::Switch);
+        let map = sim.state.ecs.fetch::<Map>();
+        assert!(map.is_visible(2, 1));
+        assert!(!map.is_visible(1, 3));
+    }
+
+    #[test]
+    fn death_costs_a_life_then_the_game() {
+        let mut sim = Simulation::with_level(1, &Level::parse("arena", ARENA).unwrap());
+        let lives = |sim: &Simulation| sim.state.ecs.fetch::<Lives>().count;
+
+        // stand still against the wall, with the monster hitting us every turn
+        let mut turns_to_first_death = None;
+        for turn in 0..100 {
+            sim.step(Action::Move(Dir::Left));
+            if lives(&sim) == 2 && turns_to_first_death.is_none() {
+                turns_to_first_death = Some(turn);
+                assert_eq!(sim.player_positions(), vec![(1, 1)], "respawned at the start");
+            }
+            if !matches!(sim.state.runstate, RunState::AwaitingInput) { break }
+        }
+        assert!(turns_to_first_death.is_some());
+        assert_eq!(lives(&sim), 0);
+        assert!(sim.state.runstate == RunState::GameOver { selection: 0 });
+    }
+}
@@ -0,0 +1,224 @@
+/**
+ * hand-authored levels.
+ *
+ * A level is a plain text grid, one character per cell:
+ * - `#` wall, `.` floor, `_` abyss
+ * - `@` where the player starts
+ * - `<` `>` `^` `v` a patrolling monster, facing that way
+ * - `E` the exit
+ * - `t` a torch
+ *
+ * Players, monsters, torches and the exit all stand on floor. Lines starting with `;` are comments.
+ * All rows must be equally wide.
+ */
+
+use std::fmt;
+
+use specs::prelude::*;
+
+use crate::{
+    geo::Point,
+    map::{Map, Tile},
+    resources::PlayerStart,
+    systems::{make_player, spawn_exit, spawn_monster, spawn_torch},
+    util::Dir,
+};
+
+/// levels that ship with the game, in the order of the levels menu
+pub const BUILTIN: &[(&str, &str)] = &[
+    ("bridge", include_str!("../levels/01-bridge.txt")),
+    ("sliding", include_str!("../levels/02-sliding.txt")),
+    ("the guard", include_str!("../levels/03-the-guard.txt")),
+];
+
+pub struct Level {
+    pub name: String,
+    pub map: Map,
+    pub player: Point,
+    pub monsters: Vec<(Point, Dir)>,
+    pub exit: Option<Point>,
+    pub torches: Vec<Point>,
+}
+
+#[derive(Debug)]
+pub struct LevelError {
+    pub line: usize, // 1-based
+    pub column: usize, // 1-based, 0 if the error is about the line as a whole
+    pub message: String,
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.column {
+            0 => write!(f, "line {}: {}", self.line, self.message),
+            _ => write!(f, "line {}, column {}: {}", self.line, self.column, self.message),
+        }
+    }
+}
+
+impl Level {
+
+    /// a built-in level by name, otherwise a level file at that path
+    pub fn load(name: &str) -> Result<Level, String> {
+        if let Some((_, text)) = BUILTIN.iter().find(|(n, _)| *n == name) {
+            return Level::parse(name, text).map_err(|e| format!("{}: {}", name, e));
+        }
+        let text = std::fs::read_to_string(name).map_err(|e| format!("{}: {}", name, e))?;
+        Level::parse(name, &text).map_err(|e| format!("{}: {}", name, e))
+    }
+
+    pub fn parse(name: &str, text: &str) -> Result<Level, LevelError> {
+
+        let rows: Vec<(usize, &str)> = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim_end()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with(';'))
+            .collect();
+
+        let (first_line, first_row) = match rows.first() {
+            Some(row) => *row,
+            None => return Err(LevelError { line: 1, column: 0, message: "level has no rows".to_string() }),
+        };
+        let width = first_row.chars().count();
+        let height = rows.len();
+
+        let mut map = Map::new(width, height, Tile::Floor, 0.0);
+        let mut player: Option<(Point, usize)> = None;
+        let mut monsters = Vec::new();
+        let mut exit: Option<Point> = None;
+        let mut torches = Vec::new();
+
+        for (y, (line, row)) in rows.iter().enumerate() {
+            let row_width = row.chars().count();
+            if row_width != width {
+                return Err(LevelError {
+                    line: *line,
+                    column: 0,
+                    message: format!("row is {} wide, but line {} is {} wide", row_width, first_line, width),
+                });
+            }
+
+            for (x, c) in row.chars().enumerate() {
+                let point = Point::new(x as i32, y as i32);
+                let error = |message: String| LevelError { line: *line, column: x + 1, message };
+                let tile = match c {
+                    '#' => Tile::Wall,
+                    '.' => Tile::Floor,
+                    '_' => Tile::Empty,
+                    '@' => {
+                        if let Some((_, other)) = player {
+                            return Err(error(format!("second player start, the first is on line {}", other)));
+                        }
+                        player = Some((point, *line));
+                        Tile::Floor
+                    }
+                    '<' | '>' | '^' | 'v' => {
+                        let dir = match c {
+                            '<' => Dir::Left,
+                            '>' => Dir::Right,
+                            '^' => Dir::Up,
+                            _ => Dir::Down,
+                        };
+                        monsters.push((point, dir));
+                        Tile::Floor
+                    }
+                    'E' => {
+                        if exit.is_some() {
+                            return Err(error("a level can only have one exit".to_string()));
+                        }
+                        exit = Some(point);
+                        Tile::Floor
+                    }
+                    't' => {
+                        torches.push(point);
+                        Tile::Floor
+                    }
+                    _ => return Err(error(format!("unknown tile '{}'", c))),
+                };
+                map.set_tile(x as i32, y as i32, tile);
+            }
+        }
+
+        let player = match player {
+            Some((point, _)) => point,
+            None => return Err(LevelError { line: first_line, column: 0, message: "level has no player start '@'".to_string() }),
+        };
+
+        Ok(Level { name: name.to_string(), map, player, monsters, exit, torches })
+    }
+
+    /// put the map resource and all entities of this level into the world
+    pub fn spawn(&self, ecs: &mut World) {
+        make_player(ecs, self.player.x, self.player.y);
+        ecs.insert(PlayerStart { pos: self.player.clone() });
+        for (pos, dir) in self.monsters.iter() {
+            spawn_monster(ecs, pos.x, pos.y, "patroller", *dir);
+        }
+        if let Some(exit) = &self.exit {
+            spawn_exit(ecs, exit.x, exit.y);
+        }
+        for torch in self.torches.iter() {
+            spawn_torch(ecs, torch.x, torch.y);
+        }
+        ecs.insert(self.map.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> LevelError {
+        match Level::parse("test", text) {
+            Ok(_) => panic!("expected an error for\n{}", text),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn parses_a_small_level() {
+        let level = Level::parse("test", "; a comment\n#####\n#@>E#\n#t..#\n#####\n").unwrap();
+        assert_eq!((level.map.width, level.map.height), (5, 4));
+        assert_eq!((level.player.x, level.player.y), (1, 1));
+        assert_eq!(level.monsters.len(), 1);
+        assert_eq!(level.monsters[0].1, Dir::Right);
+        assert_eq!(level.exit.map(|p| (p.x, p.y)), Some((3, 1)));
+        assert_eq!(level.torches.len(), 1);
+    }
+
+    #[test]
+    fn builtin_levels_parse() {
+        for (name, text) in BUILTIN {
+            assert!(Level::parse(name, text).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn unknown_tile() {
+        let err = error("; comments count as lines\n#####\n#@.x#\n#####\n");
+        assert_eq!((err.line, err.column), (3, 4));
+        assert_eq!(err.message, "unknown tile 'x'");
+    }
+
+    #[test]
+    fn missing_player() {
+        let err = error("\n#####\n#...#\n#####\n");
+        assert_eq!((err.line, err.column), (2, 0));
+        assert_eq!(err.to_string(), "line 2: level has no player start '@'");
+    }
+
+    #[test]
+    fn second_player() {
+        let err = error("#####\n#@..#\n#..@#\n#####\n");
+        assert_eq!((err.line, err.column), (3, 4));
+        assert_eq!(err.to_string(), "line 3, column 4: second player start, the first is on line 2");
+    }
+
+    #[test]
+    fn ragged_rows() {
+        let err = error("#####\n#@..#\n#...\n#####\n");
+        assert_eq!((err.line, err.column), (3, 0));
+        assert_eq!(err.message, "row is 4 wide, but line 1 is 5 wide");
+    }
+}
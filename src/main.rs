@@ -11,8 +11,10 @@ use components::Solid;
 use rltk::{Rltk, RGB};
 use specs::prelude::*;
 
+mod args;
 mod resources;
 mod geo;
+mod pathing;
 mod map;
 mod cons;
 mod util;
@@ -20,32 +22,37 @@ mod util;
 mod systems;
 mod components;
 mod state;
+mod recording;
+mod headless;
+mod saveload;
+mod levels;
+mod raws;
+mod menu;
 
 use geo::Circle;
 use geo::Point;
 use geo::Line;
 use util::Dir;
 
+use crate::args::Args;
+use crate::headless::Simulation;
+use crate::levels::Level;
 use crate::components::Direction;
 use crate::components::Monster;
 use crate::components::Player;
 use crate::components::Projectile;
 use crate::map::Map;
+use crate::recording::Recording;
+use crate::recording::Replay;
+use crate::raws::Raws;
 use crate::resources::Camera;
 use crate::resources::Lives;
 use crate::resources::PlayerPos;
+use crate::resources::Seed;
 use crate::state::MyState;
+use crate::state::RunState;
 use crate::systems::spawn_monsters;
 
-fn print_menu(ctx : &mut Rltk) {
-    ctx.print(4, cons::HH + 0, "Welcome, Dungeoneer!");
-    ctx.print(4, cons::HH + 1, "|------------------|");
-    ctx.print(4, cons::HH + 3, "> Play");
-    ctx.print(4, cons::HH + 4, "  Levels");
-    ctx.print(4, cons::HH + 5, "  Options");
-    ctx.print(4, cons::HH + 6, "  Quit");
-}
-
 fn spawn(ecs: &mut World, x: i32, y: i32, c: char) {
     ecs
     .create_entity()
@@ -103,49 +110,62 @@ fn drawing_things(ecs: &mut World) {
 
 /////////////////////////////////////////////////////////////////
 
-fn make_player(ecs: &mut World) {
-    ecs
-        .create_entity()
-        .with(Position::new(3, 3))
-        .with(Renderable::new(
-            rltk::to_cp437('►'), 
-            RGB::named(rltk::YELLOW), 
-            cons::RGB_BACKGROUND))
-        .with(Player {})
-        .with(Direction { dir: Dir::Down})
-        .with(Solid {})
-        .with(CombatStats { max_hp: 3, hp: 3, defense: 0, power: 1 })
-        .build();
+/// a broken file on the command line: say why, and stop
+fn fail(err: &str) -> ! {
+    eprintln!("{}", err);
+    std::process::exit(1)
 }
 
 fn main() -> rltk::BError {
 
+    let args = Args::parse().unwrap_or_else(|err| fail(&err));
+
+    // a replay brings its own seed
+    let replay = args.replay.as_ref().map(|path| Recording::load(path).unwrap_or_else(|err| fail(&err)));
+
+    // one seed drives everything random: the maze, the monsters, and their choices
+    let seed = match &replay {
+        Some(recording) => recording.seed,
+        None => args.seed.unwrap_or_else(|| rltk::RandomNumberGenerator::new().next_u64()),
+    };
+    rltk::console::log(&format!("seed: {}", seed));
+
+    // a replay also brings its own level
+    let level_name = replay.as_ref().and_then(|r| r.level.clone()).or(args.level.clone());
+    let level = level_name.map(|name| Level::load(&name).unwrap_or_else(|err| fail(&err)));
+
+    if args.headless {
+        let mut sim = match &level {
+            Some(level) => Simulation::with_level(seed, level),
+            None => Simulation::new(seed),
+        };
+        if let Some(recording) = &replay {
+            recording.check_raws(&sim.state.ecs.fetch::<Raws>()).unwrap_or_else(|err| fail(&err));
+            sim.run(&recording.actions);
+        }
+        print!("{}", sim.to_text());
+        println!("turns: {}, players: {:?}, monsters: {}", 
+            sim.turns(), sim.player_positions(), sim.monster_positions().len());
+        return Ok(());
+    }
+
     // init the state
-    let mut gs = MyState::new();
-
-    // register all used components
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Direction>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Projectile>();
-    gs.ecs.register::<Monster>();
-    gs.ecs.register::<Solid>();
-    gs.ecs.register::<CombatStats>();
-
-    // create the player
-    make_player(&mut gs.ecs);
-
-    // create the map resource
-    let maze = Map::new_maze(cons::WIDTH, cons::HEIGHT);
-    spawn_monsters(&mut gs, &maze, 5);
-    gs.ecs.insert(maze);
-
-    // create other resources
-    gs.ecs.insert(Camera { offset: Point::new(0,0) });
-    gs.ecs.insert(PlayerPos { pos: Point::new(0,0) });
-    gs.ecs.insert(Lives { count: 3, max: 3 });
-    gs.ecs.insert(rltk::RandomNumberGenerator::new());
+    // a level or replay from the command line skips the menu
+    let mut gs = match (&level, &replay) {
+        (Some(level), _) => MyState::new_level(seed, level),
+        (None, Some(_)) => MyState::new_game(seed),
+        (None, None) => {
+            let mut gs = MyState::new(seed);
+            gs.next_seed = Some(seed);
+            gs.runstate = RunState::MainMenu { selection: 0 };
+            gs
+        }
+    };
+    gs.record_path = args.record.clone();
+    if let Some(recording) = &replay {
+        recording.check_raws(&gs.ecs.fetch::<Raws>()).unwrap_or_else(|err| fail(&err));
+    }
+    gs.replay = replay.as_ref().map(Replay::new);
 
     // spawn the window
     use rltk::RltkBuilder;
@@ -1,29 +1,53 @@
-use crate::{cons, util::{Dir}, components::Position, geo::Point};
+use crate::{cons, util::{Dir}, components::Position, geo::{Line, Point}};
 use rand::prelude::SliceRandom;
 use rltk::{RGB, RandomNumberGenerator, console};
+use specs::Entity;
+use serde::{Serialize, Deserialize};
 
-// TODO implement a two layer system
-#[derive(PartialEq, Clone, Copy)]
+/// the terrain layer. Whatever stands on top of it lives in the occupancy layer
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Tile {
     Wall,
     Floor,
     Empty,
-    Entity,
 }
 
-#[derive(PartialEq)]
+/// what got moved by a push
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PushedKind {
+    Wall,
+    Entity(Entity),
+}
+
+/// one object that moved during a push. Entities are moved on the occupancy layer already, their positions still need to follow
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct PushedObject {
+    pub kind: PushedKind,
+    pub from: Point,
+    pub to: Point,
+}
+
+#[derive(PartialEq, Debug)]
 pub enum PushResult {
     Free, // nothing to push to begin with
-    Pushed, // we just pushed something to the next tile
+    Pushed(Vec<PushedObject>), // we just pushed a row of things one tile further
     Blocked, // something could be pushed, but was blocked by something standing behind it
-    Tumble, // we just pushed something down to a lower level
+    Crushed(Vec<Entity>), // the row did not budge, and these entities got squeezed against a wall
+    Tumble(Vec<PushedObject>), // we pushed a row, and the front one went down to a lower level
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Map {
     pub width: usize,
     pub height: usize,
     pub tiles: Vec<Tile>,
-    pub light: Vec<f32>
+    #[serde(skip)]
+    pub occupants: Vec<Vec<Entity>>, // which solid entities stand on each cell, rebuilt by MapIndexing
+    pub light: Vec<(f32, f32, f32)>, // the colored light falling on each cell, summed over all sources
+    #[serde(skip)]
+    pub visible: Vec<bool>, // what the player sees right now, filled by the VisibilitySystem
+    pub revealed: Vec<bool>, // everything the player has ever seen lit up
+    pub revision: u32, // goes up whenever the terrain changes, so paths know when they are outdated. Saved along with them
 }
 
 // basic data methods
@@ -31,13 +55,19 @@ impl Map {
 
     pub fn new(width: usize, height: usize, def_tile: Tile, def_light: f32) -> Self {
         let tiles = vec![def_tile; width * height];
-        let light = vec![def_light; width * height];
-        Self {width, height, tiles, light}
+        let occupants = vec![Vec::new(); width * height];
+        let light = vec![(def_light, def_light, def_light); width * height];
+        let visible = vec![false; width * height];
+        let revealed = vec![false; width * height];
+        Self {width, height, tiles, occupants, light, visible, revealed, revision: 0}
     }
 
     pub fn set_tile(&mut self, x: i32, y: i32, tile: Tile) -> Option<usize> {
         let id = self.to_index(x, y)?;
-        self.tiles[id] = tile;
+        if self.tiles[id] != tile {
+            self.tiles[id] = tile;
+            self.revision = self.revision.wrapping_add(1);
+        }
         Some(id)
     }
 
@@ -50,15 +80,86 @@ impl Map {
         self.get_tile(point.x, point.y)
     }
 
-    pub fn set_light(&mut self, x: i32, y: i32, light: f32) -> Option<usize> {
+    pub fn get_occupants(&self, x: i32, y: i32) -> &[Entity] {
+        match self.to_index(x, y) {
+            Some(id) => &self.occupants[id],
+            None => &[],
+        }
+    }
+
+    pub fn get_occupants_at(&self, point: Point) -> &[Entity] {
+        self.get_occupants(point.x, point.y)
+    }
+
+    pub fn add_occupant(&mut self, x: i32, y: i32, entity: Entity) -> Option<usize> {
+        let id = self.to_index(x, y)?;
+        self.occupants[id].push(entity);
+        Some(id)
+    }
+
+    pub fn remove_occupant(&mut self, x: i32, y: i32, entity: Entity) -> Option<usize> {
+        let id = self.to_index(x, y)?;
+        self.occupants[id].retain(|e| *e != entity);
+        Some(id)
+    }
+
+    pub fn is_occupied(&self, x: i32, y: i32) -> bool {
+        !self.get_occupants(x, y).is_empty()
+    }
+
+    pub fn add_light(&mut self, x: i32, y: i32, color: (f32, f32, f32)) -> Option<usize> {
         let id = self.to_index(x, y)?;
-        self.light[id] = light;
+        let light = &mut self.light[id];
+        light.0 += color.0;
+        light.1 += color.1;
+        light.2 += color.2;
         Some(id)
     }
 
+    /// how bright it is here: the strongest channel of the light
     pub fn get_light(&self, x: i32, y: i32) -> Option<f32> {
         let id = self.to_index(x, y)?;
-        Some(self.light[id])
+        Some(brightness(self.light[id]))
+    }
+
+    pub fn get_light_color(&self, x: i32, y: i32) -> Option<RGB> {
+        let id = self.to_index(x, y)?;
+        let (r, g, b) = self.light[id];
+        Some(RGB::from_f32(r.min(1.0), g.min(1.0), b.min(1.0)))
+    }
+
+    pub fn is_visible(&self, x: i32, y: i32) -> bool {
+        match self.to_index(x, y) {
+            Some(id) => self.visible.get(id).copied().unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// only these cells are visible from now on
+    pub fn set_visible(&mut self, cells: &[usize]) {
+        self.visible.clear();
+        self.visible.resize(self.size(), false);
+        for id in cells {
+            if let Some(v) = self.visible.get_mut(*id) {
+                *v = true;
+            }
+        }
+    }
+
+    /// remember every cell that is both visible and lit right now
+    pub fn reveal_seen(&mut self) {
+        for (i, light) in self.light.iter().enumerate() {
+            if brightness(*light) > 0.0 && self.visible.get(i).copied().unwrap_or(false) {
+                self.revealed[i] = true;
+            }
+        }
+    }
+
+    pub fn is_revealed(&self, x: i32, y: i32) -> bool {
+        match self.to_index(x, y) {
+            Some(id) => self.revealed[id],
+            None => false,
+        }
     }
 
     pub fn to_index(&self, x: i32, y: i32) -> Option<usize> {
@@ -81,7 +182,7 @@ impl Map {
 // more high level map methods
 impl Map {
 
-    pub fn new_random(width: usize, height: usize, num_walls: u32, num_holes: u32) -> Map {
+    pub fn new_random(width: usize, height: usize, num_walls: u32, num_holes: u32, rng: &mut RandomNumberGenerator) -> Map {
 
         let mut map = Map::new(width, height, Tile::Floor, 0.0);
 
@@ -97,8 +198,6 @@ impl Map {
             map.set_tile(w-1, i, Tile::Wall);
         } 
     
-        let mut rng = rltk::RandomNumberGenerator::new();
-    
         for _i in 0..num_walls as i32 {
             let x = rng.roll_dice(1, w-2);
             let y = rng.roll_dice(1, h-2);
@@ -136,7 +235,8 @@ impl Map {
     }
 
     /// a elaborate procedure to just create a nice, maze like map.
-    pub fn new_maze(width: usize, height: usize) -> Map {
+    /// the same rng state always produces the same maze
+    pub fn new_maze(width: usize, height: usize, rng: &mut RandomNumberGenerator) -> Map {
         
         fn to_even(n: i32) -> i32 {
             n / 2 * 2
@@ -182,7 +282,6 @@ impl Map {
         let mut maze = Self::new_empty(width, height, Tile::Wall, true);
         
         // build a bunch of agents 
-        let mut rng = RandomNumberGenerator::new();
         let mut positions: Vec<(Position, bool)> = Vec::new();
 
         for _ in 0..num_agents {
@@ -198,7 +297,7 @@ impl Map {
         // let them walk around, digging tunnels
         for _ in 0..num_iterations {
             for (a, continuous) in positions.iter_mut() {
-                let dir = match try_select(&mut rng, &a, &maze) {
+                let dir = match try_select(rng, &a, &maze) {
                     Some(dir) => dir,
                     None => {
                         // what to do if all directions are already visisted?
@@ -237,7 +336,13 @@ impl Map {
 
     /////////////////////////////////////////////////////////////////
 
+    /// can something stand here? only on floor that nobody else is standing on
     pub fn is_free(&self, x: i32, y: i32) -> bool {
+        self.is_floor(x, y) && !self.is_occupied(x, y)
+    }
+
+    /// terrain only, ignores the occupancy layer
+    pub fn is_floor(&self, x: i32, y: i32) -> bool {
         let t = self.get_tile(x, y).unwrap_or(Tile::Wall);
         t == Tile::Floor
     }
@@ -246,51 +351,153 @@ impl Map {
         return self.is_free(point.x, point.y)
     }
 
+    /// the floor cells one step away, to find paths over
+    pub fn exits(&self, id: usize) -> Vec<usize> {
+        let (x, y) = self.to_coord(id);
+        [Dir::Left, Dir::Down, Dir::Right, Dir::Up].iter()
+            .map(|d| d.xy())
+            .filter(|(dx, dy)| self.is_floor(x + dx, y + dy))
+            .filter_map(|(dx, dy)| self.to_index(x + dx, y + dy))
+            .collect()
+    }
+
     pub fn darken_all(&mut self) {
-        self.light.fill(0.0)
+        self.light.fill((0.0, 0.0, 0.0))
     }
 
-    /////////////////////////////////////////////////////////////////
+    /// every cell within `reach` for which `in_view` holds, with a clear line towards it from the origin.
+    /// Walls block the line, but are seen themselves.
+    /// NOTE: casting only towards the edge of a circle leaves gaps, so every cell gets its own line
+    pub fn cells_in_sight(&self, origin: Point, reach: i32, in_view: impl Fn(&Point) -> bool) -> Vec<usize> {
+        let mut visible: Vec<usize> = self.to_index(origin.x, origin.y).into_iter().collect();
+        for y in origin.y - reach..=origin.y + reach {
+            for x in origin.x - reach..=origin.x + reach {
+                let p = Point::new(x, y);
+                let id = match self.to_index(x, y) {
+                    Some(id) => id,
+                    None => continue,
+                };
+                if p == origin || !in_view(&p) { continue }
+                let line = Line::new(origin, p).to_grid();
+                let clear = line[1..line.len() - 1].iter().all(|c| self.get_tile(c.x, c.y) != Some(Tile::Wall));
+                if clear {
+                    visible.push(id);
+                }
+            }
+        }
 
-    pub fn clear_all_entities(&mut self) {
-        for tile in self.tiles.iter_mut() {
-            if *tile == Tile::Entity { 
-                *tile = Tile::Floor; 
+        // lines running along a wall clip its corners, so also take the walls right next to what is in sight
+        let mut faces = Vec::new();
+        for id in visible.iter() {
+            let (x, y) = self.to_coord(*id);
+            if self.get_tile(x, y) == Some(Tile::Wall) { continue }
+            for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let p = Point::new(x + dx, y + dy);
+                if self.get_tile(p.x, p.y) == Some(Tile::Wall) && in_view(&p) {
+                    faces.extend(self.to_index(p.x, p.y));
+                }
             }
         }
+        visible.extend(faces);
+        visible.sort_unstable();
+        visible.dedup();
+        visible
     }
 
-    pub fn apply_entity(&mut self, x: i32, y: i32) {
-        self.set_tile(x, y, Tile::Entity);
+    /////////////////////////////////////////////////////////////////
+
+    pub fn clear_occupants(&mut self) {
+        // a freshly loaded map has no occupancy layer at all
+        self.occupants.resize(self.size(), Vec::new());
+        for cell in self.occupants.iter_mut() {
+            cell.clear();
+        }
     }
 
     /////////////////////////////////////////////////////////////////
 
-    pub fn apply_push_effect(&mut self, x: i32, y: i32, dir: Dir) -> PushResult {
+    /// can this cell take part in a push? walls, and everything standing on the floor
+    pub fn is_pushable(&self, x: i32, y: i32) -> bool {
+        self.get_tile(x, y) == Some(Tile::Wall) || self.is_occupied(x, y)
+    }
+
+    /// the row of pushable cells starting at x, y, and the tile right after it
+    fn push_row(&self, x: i32, y: i32, dir: Dir) -> (Vec<(i32, i32)>, Option<Tile>) {
+        let (dx, dy) = dir.xy();
+        let mut row: Vec<(i32, i32)> = Vec::new();
+        let (mut cx, mut cy) = (x, y);
+        while self.is_pushable(cx, cy) {
+            row.push((cx, cy));
+            cx += dx;
+            cy += dy;
+        }
+        (row, self.get_tile(cx, cy))
+    }
+
+    /// would a push at x, y move anything?
+    pub fn can_push(&self, x: i32, y: i32, dir: Dir, strength: usize) -> bool {
+        let (row, after) = self.push_row(x, y, dir);
+        !row.is_empty() && row.len() <= strength && after.is_some()
+    }
+
+    /// push the row of walls and entities starting at x, y one cell further.
+    /// `strength` is the longest row that can be moved. The whole row moves, or nothing does.
+    pub fn apply_push_effect(&mut self, x: i32, y: i32, dir: Dir, strength: usize) -> PushResult {
     
         let (dx, dy) = dir.xy();
-        let tile = self.get_tile(x, y).unwrap_or(Tile::Wall);
-        if tile != Tile::Floor { // bump into something?
-            if tile == Tile::Wall { // bump into wall?
-                let afterwall = self.get_tile(x+dx, y+dy).unwrap_or(Tile::Wall);
-                if afterwall == Tile::Wall { return PushResult::Blocked }
-                if afterwall == Tile::Floor { // after wall floor? push.
-                    self.set_tile(x, y, Tile::Floor);
-                    self.set_tile(x+dx, y+dy, Tile::Wall);
-                    return PushResult::Pushed;
-                }
-                if afterwall == Tile::Empty { // after wall abyss? push it in
-                    self.set_tile(x, y, Tile::Floor);
-                    self.set_tile(x+dx, y+dy, Tile::Floor);
-                    return PushResult::Tumble;
+        let (row, after) = self.push_row(x, y, dir);
+
+        if row.is_empty() {
+            // next tile is free! unless its the abyss, or the edge of the world
+            return match self.get_tile(x, y) {
+                Some(Tile::Floor) => PushResult::Free,
+                _ => PushResult::Blocked,
+            };
+        }
+
+        // too heavy, or pushed against the edge of the world? 
+        // whoever is pressed against a wall in the row gets crushed
+        if row.len() > strength || after.is_none() {
+            let mut crushed = Vec::new();
+            for (cx, cy) in row.iter() {
+                let behind = self.get_tile(cx + dx, cy + dy).unwrap_or(Tile::Wall);
+                if behind == Tile::Wall {
+                    crushed.extend_from_slice(self.get_occupants(*cx, *cy));
                 }
-            } 
-            // bump into something else? dont go there
-            return PushResult::Blocked;
-        };
-    
-        // next tile is free!
-        return PushResult::Free;
+            }
+            return match crushed.is_empty() {
+                true => PushResult::Blocked,
+                false => PushResult::Crushed(crushed),
+            };
+        }
+
+        // move everything one cell, starting at the front, so there is always room
+        let tumble = after == Some(Tile::Empty);
+        let mut moved = Vec::new();
+        for (i, (cx, cy)) in row.into_iter().rev().enumerate() {
+            let from = Point::new(cx, cy);
+            let to = Point::new(cx + dx, cy + dy);
+            let front = i == 0;
+
+            if self.get_tile(cx, cy) == Some(Tile::Wall) {
+                // a wall going into the abyss fills it up
+                let tile = if front && tumble { Tile::Floor } else { Tile::Wall };
+                self.set_tile(cx, cy, Tile::Floor);
+                self.set_tile(to.x, to.y, tile);
+                moved.push(PushedObject { kind: PushedKind::Wall, from, to });
+            }
+
+            let id = self.to_index(cx, cy).unwrap();
+            for e in std::mem::take(&mut self.occupants[id]) {
+                self.add_occupant(to.x, to.y, e);
+                moved.push(PushedObject { kind: PushedKind::Entity(e), from, to });
+            }
+        }
+
+        match tumble {
+            true => PushResult::Tumble(moved),
+            false => PushResult::Pushed(moved),
+        }
     }
 
     /////////////////////////////////////////////////////////////////
@@ -302,10 +509,12 @@ impl Map {
         let mut y = 0;
         let mut x = 0;
 
-        for (tile, light) in self.tiles.iter().zip(self.light.iter()) {
+        for (i, (tile, light)) in self.tiles.iter().zip(self.light.iter()).enumerate() {
             
-            // Render a tile depending upon the tile type
-            if *light > 0.0 { 
+            // seen right now, lit by whatever light falls on it. Or just remembered, in grey
+            let seen = brightness(*light) > 0.0 && self.visible.get(i).copied().unwrap_or(false);
+            if seen || self.revealed[i] { 
+                let tint = RGB::from_f32(light.0.min(1.0), light.1.min(1.0), light.2.min(1.0));
 
                 let (fg, bg, glyph) = match tile {
                     Tile::Empty => (cons::RGB_BACKGROUND, black.clone(), rltk::to_cp437(' ')),
@@ -318,13 +527,17 @@ impl Map {
                         );
                         (RGB::from_u8(100, 100, 200), cons::RGB_BACKGROUND, rltk::to_cp437(char))
                     }  
-                    _ => (cons::RGB_BACKGROUND, cons::RGB_BACKGROUND, rltk::to_cp437(' ')),
+                    Tile::Floor => (cons::RGB_BACKGROUND, cons::RGB_BACKGROUND, rltk::to_cp437(' ')),
                 };
 
+                let (fg, bg) = match seen {
+                    true => (fg * tint, bg * tint),
+                    false => (fg.to_greyscale() * cons::REMEMBERED_BRIGHTNESS, bg.to_greyscale() * cons::REMEMBERED_BRIGHTNESS),
+                };
                 ctx.set(x + offset.x * 1, 
                     y + offset.y * 1, 
-                    RGB::lerp(&black, fg, *light), 
-                    RGB::lerp(&black, bg, *light), 
+                    fg, 
+                    bg, 
                     glyph);
 
             };
@@ -371,3 +584,145 @@ fn getwall(up: Tile, left: Tile, bot: Tile, right: Tile) -> char {
         _ => '■'
     }
 }
+
+/// the strongest channel of a light
+fn brightness(light: (f32, f32, f32)) -> f32 {
+    light.0.max(light.1).max(light.2).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::{World, WorldExt, Builder};
+
+    /// a one row map: `#` wall, `.` floor, `_` abyss, and a letter for an entity standing on floor
+    fn row(text: &str) -> (Map, Vec<Entity>) {
+        let mut world = World::new();
+        let mut map = Map::new(text.len(), 1, Tile::Floor, 0.0);
+        let mut entities = Vec::new();
+        for (x, c) in text.chars().enumerate() {
+            match c {
+                '#' => { map.set_tile(x as i32, 0, Tile::Wall); }
+                '_' => { map.set_tile(x as i32, 0, Tile::Empty); }
+                '.' => {}
+                _ => {
+                    let e = world.create_entity().build();
+                    map.add_occupant(x as i32, 0, e);
+                    entities.push(e);
+                }
+            }
+        }
+        (map, entities)
+    }
+
+    /// the reverse of row, with the entities lettered in the order they were made
+    fn show(map: &Map, entities: &[Entity]) -> String {
+        (0..map.width as i32).map(|x| {
+            if let Some(&e) = map.get_occupants(x, 0).first() {
+                return (b'a' + entities.iter().position(|o| *o == e).unwrap() as u8) as char;
+            }
+            match map.get_tile(x, 0) {
+                Some(Tile::Wall) => '#',
+                Some(Tile::Empty) => '_',
+                _ => '.',
+            }
+        }).collect()
+    }
+
+    fn moved(kind: PushedKind, from: i32, to: i32) -> PushedObject {
+        PushedObject { kind, from: Point::new(from, 0), to: Point::new(to, 0) }
+    }
+
+    #[test]
+    fn pushing_nothing() {
+        let (mut map, _) = row("#.._");
+        assert_eq!(map.apply_push_effect(1, 0, Dir::Right, 1), PushResult::Free);
+        assert_eq!(map.apply_push_effect(3, 0, Dir::Right, 1), PushResult::Blocked, "the abyss can not be stood on");
+    }
+
+    #[test]
+    fn pushing_an_entity() {
+        let (mut map, e) = row("#a.#");
+        let result = map.apply_push_effect(1, 0, Dir::Right, 1);
+        assert_eq!(result, PushResult::Pushed(vec![moved(PushedKind::Entity(e[0]), 1, 2)]));
+        assert_eq!(show(&map, &e), "#.a#");
+    }
+
+    #[test]
+    fn crushed_against_a_wall() {
+        let (mut map, e) = row("#.a##");
+        assert_eq!(map.apply_push_effect(2, 0, Dir::Right, 5), PushResult::Crushed(vec![e[0]]));
+        assert_eq!(show(&map, &e), "#.a##", "nothing moved");
+    }
+
+    #[test]
+    fn tumbling_into_the_abyss() {
+        let (mut map, e) = row("#a_#");
+        let result = map.apply_push_effect(1, 0, Dir::Right, 1);
+        assert_eq!(result, PushResult::Tumble(vec![moved(PushedKind::Entity(e[0]), 1, 2)]));
+        assert_eq!(map.get_occupants(2, 0), &[e[0]]);
+        assert_eq!(map.get_tile(2, 0), Some(Tile::Empty), "the abyss stays, the entity falls");
+    }
+
+    #[test]
+    fn a_wall_fills_the_abyss() {
+        let (mut map, e) = row(".#_");
+        let result = map.apply_push_effect(1, 0, Dir::Right, 1);
+        assert_eq!(result, PushResult::Tumble(vec![moved(PushedKind::Wall, 1, 2)]));
+        assert_eq!(show(&map, &e), "...");
+    }
+
+    #[test]
+    fn a_row_moves_as_one() {
+        let (mut map, e) = row(".abc.");
+        let result = map.apply_push_effect(1, 0, Dir::Right, 3);
+        assert_eq!(result, PushResult::Pushed(vec![
+            moved(PushedKind::Entity(e[2]), 3, 4),
+            moved(PushedKind::Entity(e[1]), 2, 3),
+            moved(PushedKind::Entity(e[0]), 1, 2),
+        ]));
+        assert_eq!(show(&map, &e), "..abc");
+    }
+
+    #[test]
+    fn a_row_too_long_does_not_budge() {
+        let (mut map, e) = row(".abc.");
+        let revision = map.revision;
+        assert_eq!(map.apply_push_effect(1, 0, Dir::Right, 2), PushResult::Blocked);
+        assert!(!map.can_push(1, 0, Dir::Right, 2));
+        assert_eq!(show(&map, &e), ".abc.");
+        assert_eq!(map.revision, revision);
+    }
+
+    #[test]
+    fn walls_and_entities_move_together() {
+        let (mut map, e) = row(".a#b.");
+        let result = map.apply_push_effect(1, 0, Dir::Right, 3);
+        assert_eq!(result, PushResult::Pushed(vec![
+            moved(PushedKind::Entity(e[1]), 3, 4),
+            moved(PushedKind::Wall, 2, 3),
+            moved(PushedKind::Entity(e[0]), 1, 2),
+        ]));
+        assert_eq!(show(&map, &e), "..a#b");
+    }
+
+    #[test]
+    fn a_mixed_row_too_long_crushes_against_its_wall() {
+        let (mut map, e) = row(".a#b.");
+        let revision = map.revision;
+        assert_eq!(map.apply_push_effect(1, 0, Dir::Right, 2), PushResult::Crushed(vec![e[0]]));
+        assert_eq!(show(&map, &e), ".a#b.");
+        assert_eq!(map.revision, revision);
+    }
+
+    #[test]
+    fn a_mixed_row_fills_the_abyss() {
+        let (mut map, e) = row(".a#_");
+        let result = map.apply_push_effect(1, 0, Dir::Right, 2);
+        assert_eq!(result, PushResult::Tumble(vec![
+            moved(PushedKind::Wall, 2, 3),
+            moved(PushedKind::Entity(e[0]), 1, 2),
+        ]));
+        assert_eq!(show(&map, &e), "..a.");
+    }
+}
@@ -0,0 +1,172 @@
+/**
+ * the screens around the game: main menu, level select, options, pause, game over and victory.
+ * Every screen is a list of entries with a cursor. Arrow keys move the cursor, enter selects, escape goes back.
+ * Each screen returns the runstate of the next frame.
+ */
+
+use rltk::{Rltk, VirtualKeyCode};
+
+use crate::{cons, levels::{self, Level}, saveload, state::{MyState, RunState}};
+
+enum MenuInput {
+    Stay(usize),
+    Select(usize),
+    Back,
+}
+
+fn menu_input(ctx: &Rltk, selection: usize, count: usize) -> MenuInput {
+    match ctx.key {
+        Some(VirtualKeyCode::Up)     => MenuInput::Stay((selection + count - 1) % count),
+        Some(VirtualKeyCode::Down)   => MenuInput::Stay((selection + 1) % count),
+        Some(VirtualKeyCode::Return) => MenuInput::Select(selection),
+        Some(VirtualKeyCode::Space)  => MenuInput::Select(selection),
+        Some(VirtualKeyCode::Escape) => MenuInput::Back,
+        _ => MenuInput::Stay(selection),
+    }
+}
+
+fn draw_menu<S: AsRef<str>>(ctx: &mut Rltk, title: &str, entries: &[S], selection: usize) {
+    ctx.print(4, cons::HH + 0, title);
+    ctx.print(4, cons::HH + 1, "|------------------|");
+    for (i, entry) in entries.iter().enumerate() {
+        let cursor = if i == selection { ">" } else { " " };
+        ctx.print(4, cons::HH + 3 + i, format!("{} {}", cursor, entry.as_ref()));
+    }
+}
+
+pub fn main_menu(gs: &mut MyState, ctx: &mut Rltk, selection: usize) -> RunState {
+    let entries = ["Play", "Levels", "Options", "Quit"];
+    draw_menu(ctx, "Welcome, Dungeoneer!", &entries, selection);
+
+    match menu_input(ctx, selection, entries.len()) {
+        MenuInput::Stay(selection) => RunState::MainMenu { selection },
+        MenuInput::Back => RunState::MainMenu { selection },
+        MenuInput::Select(0) => gs.start(None),
+        MenuInput::Select(1) => RunState::LevelSelect { selection: 0 },
+        MenuInput::Select(2) => RunState::Options { selection: 0 },
+        MenuInput::Select(_) => {
+            ctx.quit();
+            RunState::MainMenu { selection }
+        }
+    }
+}
+
+pub fn level_select(gs: &mut MyState, ctx: &mut Rltk, selection: usize) -> RunState {
+    let mut entries: Vec<&str> = levels::BUILTIN.iter().map(|(name, _)| *name).collect();
+    entries.push("Back");
+    draw_menu(ctx, "Levels", &entries, selection);
+
+    match menu_input(ctx, selection, entries.len()) {
+        MenuInput::Stay(selection) => RunState::LevelSelect { selection },
+        MenuInput::Back => RunState::MainMenu { selection: 1 },
+        MenuInput::Select(i) if i == levels::BUILTIN.len() => RunState::MainMenu { selection: 1 },
+        MenuInput::Select(i) => start_level(gs, levels::BUILTIN[i].0, RunState::LevelSelect { selection }),
+    }
+}
+
+pub fn options(gs: &mut MyState, ctx: &mut Rltk, selection: usize) -> RunState {
+    let on_off = |b: bool| if b { "on" } else { "off" };
+    let entries = [
+        format!("Show seed: {}", on_off(gs.options.show_seed)),
+        format!("Record:    {}", on_off(gs.record_path.is_some())),
+        format!("Light:     {}", gs.options.falloff.name()),
+        "Back".to_string(),
+    ];
+    draw_menu(ctx, "Options", &entries, selection);
+
+    // left and right also flip a setting
+    let input = match ctx.key {
+        Some(VirtualKeyCode::Left) | Some(VirtualKeyCode::Right) => MenuInput::Select(selection),
+        _ => menu_input(ctx, selection, entries.len()),
+    };
+    match input {
+        MenuInput::Stay(selection) => RunState::Options { selection },
+        MenuInput::Back | MenuInput::Select(3) => RunState::MainMenu { selection: 2 },
+        MenuInput::Select(0) => {
+            gs.options.show_seed = !gs.options.show_seed;
+            RunState::Options { selection }
+        }
+        MenuInput::Select(1) => {
+            gs.record_path = match gs.record_path {
+                Some(_) => None,
+                None => Some(cons::RECORD_PATH.to_string()),
+            };
+            RunState::Options { selection }
+        }
+        MenuInput::Select(_) => {
+            gs.options.falloff = gs.options.falloff.next();
+            RunState::Options { selection }
+        }
+    }
+}
+
+pub fn paused(gs: &mut MyState, ctx: &mut Rltk, selection: usize) -> RunState {
+    let entries = ["Resume", "Save", "Load", "Main menu"];
+    draw_menu(ctx, "Paused", &entries, selection);
+
+    match menu_input(ctx, selection, entries.len()) {
+        MenuInput::Stay(selection) => RunState::Paused { selection },
+        MenuInput::Back | MenuInput::Select(0) => RunState::AwaitingInput,
+        MenuInput::Select(1) => {
+            gs.save();
+            RunState::AwaitingInput
+        }
+        MenuInput::Select(2) if saveload::has_save() => {
+            gs.load();
+            RunState::AwaitingInput
+        }
+        MenuInput::Select(2) => RunState::Paused { selection },
+        MenuInput::Select(_) => RunState::MainMenu { selection: 0 },
+    }
+}
+
+pub fn game_over(gs: &mut MyState, ctx: &mut Rltk, selection: usize) -> RunState {
+    let entries = ["Try again", "Main menu"];
+    draw_menu(ctx, "Game over", &entries, selection);
+
+    match menu_input(ctx, selection, entries.len()) {
+        MenuInput::Stay(selection) => RunState::GameOver { selection },
+        MenuInput::Select(0) => restart(gs, RunState::GameOver { selection }),
+        MenuInput::Back | MenuInput::Select(_) => RunState::MainMenu { selection: 0 },
+    }
+}
+
+pub fn victory(gs: &mut MyState, ctx: &mut Rltk, selection: usize) -> RunState {
+    let next = next_level(gs);
+    let entries = [if next.is_some() { "Next level" } else { "Play again" }, "Main menu"];
+    draw_menu(ctx, "Victory!", &entries, selection);
+
+    match menu_input(ctx, selection, entries.len()) {
+        MenuInput::Stay(selection) => RunState::Victory { selection },
+        MenuInput::Select(0) => match next {
+            Some(name) => start_level(gs, name, RunState::Victory { selection }),
+            None => restart(gs, RunState::Victory { selection }),
+        },
+        MenuInput::Back | MenuInput::Select(_) => RunState::MainMenu { selection: 0 },
+    }
+}
+
+/// play the current level (or a fresh maze) again
+fn restart(gs: &mut MyState, fallback: RunState) -> RunState {
+    match gs.recording.level.clone() {
+        Some(name) => start_level(gs, &name, fallback),
+        None => gs.start(None),
+    }
+}
+
+fn start_level(gs: &mut MyState, name: &str, fallback: RunState) -> RunState {
+    match Level::load(name) {
+        Ok(level) => gs.start(Some(&level)),
+        Err(err) => {
+            rltk::console::log(&format!("could not load level: {}", err));
+            fallback
+        }
+    }
+}
+
+/// the built-in level after the one being played, if any
+fn next_level(gs: &MyState) -> Option<&'static str> {
+    let current = gs.recording.level.as_ref()?;
+    let index = levels::BUILTIN.iter().position(|(name, _)| name == current)?;
+    levels::BUILTIN.get(index + 1).map(|(name, _)| *name)
+}
@@ -0,0 +1,50 @@
+/**
+ * Finding the way around the map. Only the terrain counts, whoever stands in the way is expected to move on eventually
+ */
+
+use std::{collections::BinaryHeap, cmp::Reverse};
+
+use crate::{map::Map, geo::Point};
+
+/// the shortest way over the floor from `from` to `to`, without `from` itself. None if there is no way
+pub fn a_star(map: &Map, from: Point, to: Point) -> Option<Vec<Point>> {
+    let start = map.to_index(from.x, from.y)?;
+    let goal = map.to_index(to.x, to.y)?;
+    if !map.is_floor(to.x, to.y) { return None }
+
+    let mut cost = vec![u32::MAX; map.size()];
+    let mut came_from = vec![usize::MAX; map.size()];
+    let mut open = BinaryHeap::new();
+    cost[start] = 0;
+    // ties go to the lower index, so monsters take the same way on every replay
+    open.push(Reverse((distance(from, to), start)));
+
+    while let Some(Reverse((_, id))) = open.pop() {
+        if id == goal {
+            let mut path = Vec::new();
+            let mut at = goal;
+            while at != start {
+                let (x, y) = map.to_coord(at);
+                path.push(Point::new(x, y));
+                at = came_from[at];
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for next in map.exits(id) {
+            let c = cost[id] + 1;
+            if c >= cost[next] { continue }
+            cost[next] = c;
+            came_from[next] = id;
+            let (x, y) = map.to_coord(next);
+            open.push(Reverse((c + distance(Point::new(x, y), to), next)));
+        }
+    }
+    None
+}
+
+/// steps needed without any walls in the way
+fn distance(a: Point, b: Point) -> u32 {
+    a.x.abs_diff(b.x) + a.y.abs_diff(b.y)
+}
@@ -0,0 +1,52 @@
+use specs::prelude::*;
+use specs_derive::Component;
+use serde::{Serialize, Deserialize};
+
+use crate::{map::Map, geo::Point, pathing::a_star};
+
+/// where a monster is headed, and the way there. Searched once, then kept for as long as it still leads there
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct ChasePath {
+    pub goal: Point,
+    pub steps: Vec<Point>, // the next step first, the goal last
+    pub revision: Option<u32>, // of the terrain the steps were found on. None if they still need to be searched
+}
+
+impl ChasePath {
+
+    pub fn new(goal: Point) -> Self {
+        Self { goal, steps: Vec::new(), revision: None }
+    }
+
+    /// head somewhere else. A goal that moved a single step just extends the path, anything else needs a new search
+    pub fn retarget(&mut self, goal: Point) {
+        if goal == self.goal { return }
+        let end = self.steps.last().copied().unwrap_or(self.goal);
+        if let Some(i) = self.steps.iter().position(|s| *s == goal) {
+            // walked back towards us
+            self.steps.truncate(i + 1);
+        } else if end.x.abs_diff(goal.x) + end.y.abs_diff(goal.y) == 1 && self.revision.is_some() {
+            self.steps.push(goal);
+        } else {
+            self.revision = None;
+        }
+        self.goal = goal;
+    }
+
+    /// the cell to step on next, standing at `from`. None once there, or when there is no way at all
+    pub fn follow(&mut self, map: &Map, from: Point) -> Option<Point> {
+        // took a step since last time
+        if self.steps.first() == Some(&from) {
+            self.steps.remove(0);
+        }
+
+        // no steps left with the terrain unchanged means we are there, or there is no way. Both stay true
+        let off_track = self.steps.first().is_some_and(|s| s.x.abs_diff(from.x) + s.y.abs_diff(from.y) != 1);
+        let outdated = self.revision != Some(map.revision) || off_track;
+        if outdated {
+            self.steps = a_star(map, from, self.goal).unwrap_or_default();
+            self.revision = Some(map.revision);
+        }
+        self.steps.first().copied()
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+
+use crate::{map::Map, geo::Point};
+
+/// how much further away from the goals a fleeing field wants to be: a magnitude above 1, negated so fleeing goes downhill.
+/// Cornered monsters rather slip past the player towards open space than wait in a dead end
+const FLEE_FACTOR: f32 = -1.2;
+
+/// a flow field: how far every floor cell is from the nearest of a couple of goals.
+/// Walking downhill from anywhere leads to a goal, so everyone heading there can share one
+#[derive(Clone, Debug, Default)]
+pub struct DistanceMap {
+    pub distances: Vec<f32>, // f32::MAX where none of the goals can be reached
+}
+
+impl DistanceMap {
+
+    /// the distances towards the goals, over the floor. Goals off the floor are ignored
+    pub fn new(map: &Map, goals: &[Point]) -> Self {
+        let mut distances = vec![f32::MAX; map.size()];
+        for goal in goals {
+            if !map.is_floor(goal.x, goal.y) { continue }
+            if let Some(id) = map.to_index(goal.x, goal.y) {
+                distances[id] = 0.0;
+            }
+        }
+        settle(map, &mut distances);
+        Self { distances }
+    }
+
+    /// a field leading away from the goals of this one. It does not just point uphill,
+    /// which would trap everyone in the nearest dead end
+    pub fn fleeing(&self, map: &Map) -> Self {
+        let mut distances: Vec<f32> = self.distances.iter()
+            .map(|d| if *d == f32::MAX { f32::MAX } else { d * FLEE_FACTOR })
+            .collect();
+        settle(map, &mut distances);
+        Self { distances }
+    }
+
+    pub fn distance(&self, map: &Map, p: Point) -> Option<f32> {
+        let d = *self.distances.get(map.to_index(p.x, p.y)?)?;
+        (d < f32::MAX).then_some(d)
+    }
+
+    /// the neighbouring floor cell that gets closest to a goal. None when already there, or no goal can be reached
+    pub fn downhill(&self, map: &Map, from: Point) -> Option<Point> {
+        let id = map.to_index(from.x, from.y)?;
+        let mut best = (*self.distances.get(id)?, None);
+        for next in map.exits(id) {
+            if self.distances[next] < best.0 {
+                best = (self.distances[next], Some(next));
+            }
+        }
+        best.1.map(|next| {
+            let (x, y) = map.to_coord(next);
+            Point::new(x, y)
+        })
+    }
+}
+
+/// lower every cell until it is at most one more than its lowest neighbour
+fn settle(map: &Map, distances: &mut [f32]) {
+    let mut open: VecDeque<usize> = (0..distances.len()).filter(|id| distances[*id] < f32::MAX).collect();
+    while let Some(id) = open.pop_front() {
+        let d = distances[id] + 1.0;
+        for next in map.exits(id) {
+            if d < distances[next] {
+                distances[next] = d;
+                open.push_back(next);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Tile;
+
+    /// `#` wall, `.` floor, rows as given
+    fn map(rows: &[&str]) -> Map {
+        let mut map = Map::new(rows[0].len(), rows.len(), Tile::Floor, 0.0);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' { map.set_tile(x as i32, y as i32, Tile::Wall); }
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn distances_go_around_walls() {
+        let map = map(&[
+            "....",
+            ".##.",
+            "....",
+        ]);
+        let field = DistanceMap::new(&map, &[Point::new(0, 0)]);
+        assert_eq!(field.distance(&map, Point::new(0, 0)), Some(0.0));
+        assert_eq!(field.distance(&map, Point::new(3, 0)), Some(3.0));
+        assert_eq!(field.distance(&map, Point::new(2, 2)), Some(4.0));
+        assert_eq!(field.distance(&map, Point::new(1, 1)), None, "walls can not be reached");
+    }
+
+    #[test]
+    fn nothing_to_reach() {
+        let map = map(&[
+            ".#.",
+        ]);
+        let field = DistanceMap::new(&map, &[Point::new(0, 0), Point::new(1, 0)]);
+        assert_eq!(field.distance(&map, Point::new(2, 0)), None, "cut off by the wall");
+        assert_eq!(field.downhill(&map, Point::new(2, 0)), None);
+        assert_eq!(field.downhill(&map, Point::new(0, 0)), None, "already there");
+    }
+
+    #[test]
+    fn downhill_leads_to_the_nearest_goal() {
+        let map = map(&[
+            ".....",
+        ]);
+        let field = DistanceMap::new(&map, &[Point::new(0, 0), Point::new(4, 0)]);
+        assert_eq!(field.downhill(&map, Point::new(1, 0)), Some(Point::new(0, 0)));
+        assert_eq!(field.downhill(&map, Point::new(3, 0)), Some(Point::new(4, 0)));
+
+        let mut p = Point::new(2, 0);
+        for _ in 0..2 {
+            p = field.downhill(&map, p).unwrap();
+        }
+        assert!(p == Point::new(0, 0) || p == Point::new(4, 0));
+    }
+
+    #[test]
+    fn fleeing_slips_past_into_the_open() {
+        // a short dead end on the left of the goal, a long way to run on the right
+        let map = map(&[&".".repeat(23)]);
+        let goal = Point::new(2, 0);
+        let away = DistanceMap::new(&map, &[goal]).fleeing(&map);
+
+        assert!(away.distance(&map, Point::new(22, 0)).unwrap() < away.distance(&map, Point::new(0, 0)).unwrap());
+        assert_eq!(away.downhill(&map, Point::new(3, 0)), Some(Point::new(4, 0)));
+        assert_eq!(away.downhill(&map, Point::new(1, 0)), Some(goal), "out of the dead end, past the goal");
+    }
+}
@@ -0,0 +1,7 @@
+mod astar;
+mod chase;
+mod dijkstra;
+
+pub use astar::*;
+pub use chase::*;
+pub use dijkstra::*;
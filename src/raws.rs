@@ -0,0 +1,193 @@
+/**
+ * entity templates, so new kinds of monsters and things need no new code.
+ *
+ * They live in `raws/entities.json`, a list of templates with a unique `name` each:
+ * - `glyph`, `fg` and optionally `bg`: how it looks. Colors are `[r, g, b]`
+ * - `stats`: its CombatStats, if it can fight
+ * - `ai`: the Behavior of a monster, only the parts that are on need to be listed
+ * - `sight`: what it can see, a `kind` (Radial, Cone or Line), `radius` and `arc`
+ * - `light`: a LightSource
+ * - `solid`: if it blocks the way
+ *
+ * The file is built into the game, but natively a `raws/entities.json` in the working directory
+ * is read instead, fresh for every new game. So templates can be tweaked without recompiling.
+ */
+
+use rltk::{RGB, console};
+use serde::Deserialize;
+use specs::prelude::*;
+
+use crate::{components::{Behavior, CombatStats, LightSource, Monster, Position, Renderable, Solid, ViewShedKind, Viewshed}, cons};
+
+pub const RAWS_PATH: &str = "raws/entities.json";
+const BUILTIN: &str = include_str!("../raws/entities.json");
+
+/// the game itself relies on these
+const REQUIRED: &[&str] = &["player", "patroller", "torch", "exit"];
+
+#[derive(Deserialize)]
+pub struct Raws {
+    pub templates: Vec<Template>,
+    #[serde(skip)]
+    pub hash: u64, // of the file, so a recording can tell whether it is replayed with the same templates
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)] // a typo should not quietly turn something off
+pub struct Template {
+    pub name: String,
+    pub glyph: char,
+    pub fg: (u8, u8, u8),
+    pub bg: Option<(u8, u8, u8)>, // the usual background if left out
+    pub stats: Option<CombatStats>,
+    pub ai: Option<Behavior>,
+    pub sight: Option<Sight>,
+    pub light: Option<LightSource>,
+    #[serde(default)]
+    pub solid: bool,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sight {
+    pub kind: ViewShedKind,
+    pub radius: f32,
+    #[serde(default = "all_around")]
+    pub arc: f32,
+}
+
+fn all_around() -> f32 {
+    cons::TWO_PI
+}
+
+impl Raws {
+
+    /// the templates on disk if there are any, the built-in ones otherwise
+    pub fn load() -> Raws {
+        // in the browser, this never finds anything
+        if let Ok(text) = std::fs::read_to_string(RAWS_PATH) {
+            match Raws::parse(&text) {
+                Ok(raws) => return raws,
+                Err(err) => console::log(&format!("{}: {}, using the built-in templates", RAWS_PATH, err)),
+            }
+        }
+        Raws::parse(BUILTIN).expect("the built-in templates are broken")
+    }
+
+    pub fn parse(text: &str) -> Result<Raws, String> {
+        let mut raws: Raws = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        raws.hash = fnv1a(text);
+        for (i, template) in raws.templates.iter().enumerate() {
+            if raws.templates[..i].iter().any(|t| t.name == template.name) {
+                return Err(format!("template '{}' is defined twice", template.name));
+            }
+        }
+        if let Some(missing) = REQUIRED.iter().find(|name| raws.get(name).is_none()) {
+            return Err(format!("template '{}' is missing", missing));
+        }
+        Ok(raws)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Template> {
+        self.templates.iter().find(|t| t.name == name)
+    }
+
+    /// the names of all templates with an ai, in the order of the file
+    pub fn monsters(&self) -> Vec<String> {
+        self.templates.iter().filter(|t| t.ai.is_some()).map(|t| t.name.clone()).collect()
+    }
+}
+
+/// a hash that stays the same across builds and platforms, unlike the std one
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// start an entity at x, y from the template with this name. Whatever only some entities have,
+/// like a Direction or being the player, is up to the caller, as is building it
+pub fn spawn_template<'a>(ecs: &'a mut World, name: &str, x: i32, y: i32) -> Result<EntityBuilder<'a>, String> {
+    let template = ecs.fetch::<Raws>().get(name).cloned().ok_or(format!("no template named '{}'", name))?;
+
+    let rgb = |(r, g, b): (u8, u8, u8)| RGB::from_u8(r, g, b);
+    let mut builder = ecs
+        .create_entity()
+        .with(Position::new(x, y))
+        .with(Renderable::new(
+            rltk::to_cp437(template.glyph),
+            rgb(template.fg),
+            template.bg.map_or(cons::RGB_BACKGROUND, rgb)));
+    if let Some(stats) = template.stats {
+        builder = builder.with(stats);
+    }
+    if let Some(behavior) = template.ai {
+        builder = builder.with(Monster::new(behavior));
+    }
+    if let Some(sight) = template.sight {
+        builder = builder.with(Viewshed::new(sight.kind, sight.radius, sight.arc));
+    }
+    if let Some(light) = template.light {
+        builder = builder.with(light);
+    }
+    if template.solid {
+        builder = builder.with(Solid {});
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the built-in file, with `extra` templates added to the front
+    fn with(extra: &str) -> String {
+        BUILTIN.replacen("\"templates\": [", &format!("\"templates\": [ {},", extra), 1)
+    }
+
+    fn error(text: &str) -> String {
+        match Raws::parse(text) {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn the_builtin_file_parses() {
+        let raws = Raws::parse(BUILTIN).unwrap();
+        for name in REQUIRED {
+            assert!(raws.get(name).is_some(), "{}", name);
+        }
+        assert!(raws.monsters().contains(&"patroller".to_string()));
+        assert!(!raws.monsters().contains(&"torch".to_string()));
+        assert!(raws.get("sleeper").unwrap().ai.unwrap().asleep);
+    }
+
+    #[test]
+    fn a_name_twice() {
+        let err = error(&with(r#"{ "name": "torch", "glyph": "t", "fg": [1, 2, 3] }"#));
+        assert_eq!(err, "template 'torch' is defined twice");
+    }
+
+    #[test]
+    fn a_required_template_missing() {
+        let err = error(r#"{ "templates": [ { "name": "player", "glyph": "@", "fg": [1, 2, 3] } ] }"#);
+        assert_eq!(err, "template 'patroller' is missing");
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let err = error(&with(r#"{ "name": "ghost", "glyph": "g", "fg": [1, 2, 3], "solidd": true }"#));
+        assert!(err.contains("unknown field `solidd`"), "{}", err);
+        let err = error(&with(r#"{ "name": "ghost", "glyph": "g", "fg": [1, 2, 3], "ai": { "hunt": true } }"#));
+        assert!(err.contains("unknown field `hunt`"), "{}", err);
+        let err = error(&with(r#"{ "name": "ghost", "glyph": "g", "fg": [1, 2, 3], "sight": { "kind": "Radial", "radius": 2.0, "range": 3 } }"#));
+        assert!(err.contains("unknown field `range`"), "{}", err);
+    }
+
+    #[test]
+    fn the_hash_follows_the_file() {
+        let builtin = Raws::parse(BUILTIN).unwrap();
+        assert_eq!(Raws::parse(BUILTIN).unwrap().hash, builtin.hash);
+        let changed = Raws::parse(&with(r#"{ "name": "ghost", "glyph": "g", "fg": [1, 2, 3] }"#)).unwrap();
+        assert_ne!(changed.hash, builtin.hash);
+    }
+}
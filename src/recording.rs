@@ -0,0 +1,140 @@
+/**
+ * recording and replaying runs.
+ * A run is fully described by its seed and the actions the player took, one per turn.
+ *
+ * file format, one line per turn:
+ * ```text
+ * seed 1234
+ * raws 5b3c1d0e9a7f2468
+ * level first steps
+ * 0 move left
+ * 1 shoot
+ * 2 power dash
+ * 3 switch
+ * 4 save
+ * ```
+ * the raws line is the hash of the entity templates the run was played with, a replay needs the same ones.
+ * The level line is only there for runs on a hand-authored level.
+ */
+
+use std::fs;
+
+use crate::{components::Power, raws::Raws, systems::Action, util::Dir};
+
+pub struct Recording {
+    pub seed: u64,
+    pub raws: Option<u64>, // None for recordings from before templates were tracked
+    pub level: Option<String>,
+    pub actions: Vec<Action>,
+}
+
+impl Recording {
+
+    pub fn new(seed: u64) -> Self {
+        Self { seed, raws: None, level: None, actions: Vec::new() }
+    }
+
+    pub fn push(&mut self, action: Action) {
+        self.actions.push(action);
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("seed {}\n", self.seed);
+        if let Some(raws) = self.raws {
+            text.push_str(&format!("raws {:016x}\n", raws));
+        }
+        if let Some(level) = &self.level {
+            text.push_str(&format!("level {}\n", level));
+        }
+        for (turn, action) in self.actions.iter().enumerate() {
+            let line = match action {
+                Action::Move(dir) => format!("{} move {}\n", turn, dir.name()),
+                Action::Shoot => format!("{} shoot\n", turn),
+                Action::Power(power) => format!("{} power {}\n", turn, power.name()),
+                Action::Switch => format!("{} switch\n", turn),
+                Action::Save => format!("{} save\n", turn),
+            };
+            text.push_str(&line);
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Recording, String> {
+        let mut lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+
+        let seed = match lines.next() {
+            Some((_, line)) => match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["seed", value] => value.parse::<u64>().map_err(|_| format!("line 1: invalid seed '{}'", value))?,
+                _ => return Err("line 1: expected 'seed <number>'".to_string()),
+            },
+            None => return Err("recording is empty".to_string()),
+        };
+
+        let mut recording = Recording::new(seed);
+        for (i, line) in lines {
+            if let Some(raws) = line.strip_prefix("raws ") {
+                let hash = u64::from_str_radix(raws.trim(), 16).map_err(|_| format!("line {}: invalid raws hash '{}'", i + 1, raws))?;
+                recording.raws = Some(hash);
+                continue;
+            }
+            if let Some(level) = line.strip_prefix("level ") {
+                recording.level = Some(level.trim().to_string());
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let action = match words[..] {
+                [_, "move", dir] => Action::Move(Dir::from_name(dir)
+                    .ok_or(format!("line {}: unknown direction '{}'", i + 1, dir))?),
+                [_, "shoot"] => Action::Shoot,
+                [_, "switch"] => Action::Switch,
+                [_, "save"] => Action::Save,
+                [_, "power", power] => Action::Power(Power::from_name(power)
+                    .ok_or(format!("line {}: unknown power '{}'", i + 1, power))?),
+                _ => return Err(format!("line {}: unknown action '{}'", i + 1, line)),
+            };
+            recording.push(action);
+        }
+        Ok(recording)
+    }
+
+    /// does this run replay the same with these templates?
+    pub fn check_raws(&self, raws: &Raws) -> Result<(), String> {
+        match self.raws {
+            Some(hash) if hash != raws.hash => Err(format!(
+                "recorded with other entity templates (raws {:016x}, these are {:016x})", hash, raws.hash)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Recording, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_text(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+/// feeds the actions of a recording back, one per turn
+pub struct Replay {
+    actions: Vec<Action>,
+    cursor: usize,
+}
+
+impl Replay {
+
+    pub fn new(recording: &Recording) -> Self {
+        Self { actions: recording.actions.clone(), cursor: 0 }
+    }
+
+    pub fn next(&mut self) -> Option<Action> {
+        let action = self.actions.get(self.cursor).copied();
+        self.cursor += 1;
+        action
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.cursor >= self.actions.len()
+    }
+}
@@ -1,4 +1,6 @@
-use crate::geo::Point;
+use serde::{Serialize, Deserialize};
+
+use crate::{geo::Point, pathing::DistanceMap};
 
 
 // global resources
@@ -12,7 +14,28 @@ pub struct PlayerPos {
     pub pos: Point,
 }
 
+/// where the player (re)spawns
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlayerStart {
+    pub pos: Point,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Lives {
     pub count: i32,
     pub max: i32,
-}
\ No newline at end of file
+}
+/// the seed the rng resource was created with. Together with the input, this reproduces a run
+pub struct Seed {
+    pub value: u64,
+}
+
+/// the flow fields all monsters share, kept up to date by the DistanceMapSystem
+#[derive(Default)]
+pub struct DistanceMaps {
+    pub to_players: DistanceMap,
+    pub from_players: DistanceMap,
+    // what they were built for
+    pub players: Vec<Point>,
+    pub revision: Option<u32>,
+}
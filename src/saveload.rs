@@ -0,0 +1,366 @@
+/**
+ * saving and loading the complete world.
+ * Everything is written as one versioned json document: the map, the resources, and a snapshot of every entity.
+ * Natively this is a file next to the executable, in the wasm build it lives in localStorage.
+ */
+
+use rltk::{RandomNumberGenerator, RGB};
+use serde::{Serialize, Deserialize};
+use specs::prelude::*;
+
+use crate::{
+    components::{Active, CombatStats, Direction, Exit, Frozen, Intent, Invulnerable, Monster, Player, Position, Powers, Projectile, Renderable, Solid, Viewshed, LightSource},
+    map::Map,
+    pathing::ChasePath,
+    recording::Recording,
+    resources::{Lives, PlayerStart, Seed, DistanceMaps},
+};
+
+/// bump this whenever the layout below changes
+const VERSION: u32 = 16;
+const SAVE_NAME: &str = "savegame.json";
+
+#[derive(Serialize, Deserialize)]
+struct SaveData {
+    version: u32,
+    seed: u64,
+    rng_seed: u64, // the rng cannot be stored, so it is reseeded with this on both save and load
+    recording: String, // the run up to this save, so loading rewinds it too
+    map: Map,
+    lives: Lives,
+    player_start: PlayerStart,
+    entities: Vec<SavedEntity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEntity {
+    position: Option<Position>,
+    direction: Option<Direction>,
+    renderable: Option<SavedRenderable>,
+    player: Option<Player>,
+    active: Option<Active>,
+    monster: Option<Monster>,
+    projectile: Option<Projectile>,
+    solid: Option<Solid>,
+    combat_stats: Option<CombatStats>,
+    exit: Option<Exit>,
+    invulnerable: Option<Invulnerable>,
+    powers: Option<Powers>,
+    frozen: Option<Frozen>,
+    viewshed: Option<Viewshed>,
+    light: Option<LightSource>,
+    intent: Option<Intent>, // shown before the save, so carried out after loading it
+    chase: Option<ChasePath>,
+    shot_by: Option<usize>, // the owner of a projectile, as an index into the saved entities
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedRenderable {
+    glyph: rltk::FontCharType,
+    foreground: (f32, f32, f32),
+    background: (f32, f32, f32),
+}
+
+impl SavedRenderable {
+
+    fn from(r: &Renderable) -> Self {
+        Self {
+            glyph: r.glyph,
+            foreground: (r.foreground.r, r.foreground.g, r.foreground.b),
+            background: (r.background.r, r.background.g, r.background.b),
+        }
+    }
+
+    fn to_renderable(&self) -> Renderable {
+        let (fr, fg, fb) = self.foreground;
+        let (br, bg, bb) = self.background;
+        Renderable::new(self.glyph, RGB::from_f32(fr, fg, fb), RGB::from_f32(br, bg, bb))
+    }
+}
+
+pub fn has_save() -> bool {
+    storage::read(SAVE_NAME).is_ok()
+}
+
+/// continue from a fresh seed, so playing on is the same as loading the save.
+/// Replays do this on Action::Save too, which keeps them in step with the original run
+pub fn reseed(ecs: &mut World) -> u64 {
+    let rng_seed = ecs.fetch_mut::<RandomNumberGenerator>().next_u64();
+    ecs.insert(RandomNumberGenerator::seeded(rng_seed));
+    rng_seed
+}
+
+/// the recording should already end in the Action::Save for this save
+pub fn save_game(ecs: &mut World, recording: &Recording) -> Result<(), String> {
+    let text = to_json(ecs, recording)?;
+    storage::write(SAVE_NAME, &text)
+}
+
+/// replace the entire world with the saved one, and hand back the recording of how it got there
+pub fn load_game(ecs: &mut World) -> Result<Recording, String> {
+    let text = storage::read(SAVE_NAME)?;
+    from_json(ecs, &text)
+}
+
+/// the save document. Reseeds the rng, like Action::Save does
+fn to_json(ecs: &mut World, recording: &Recording) -> Result<String, String> {
+
+    let rng_seed = reseed(ecs);
+
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let directions = ecs.read_storage::<Direction>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let players = ecs.read_storage::<Player>();
+    let actives = ecs.read_storage::<Active>();
+    let monsters = ecs.read_storage::<Monster>();
+    let projectiles = ecs.read_storage::<Projectile>();
+    let solids = ecs.read_storage::<Solid>();
+    let stats = ecs.read_storage::<CombatStats>();
+    let exits = ecs.read_storage::<Exit>();
+    let invulnerables = ecs.read_storage::<Invulnerable>();
+    let powers = ecs.read_storage::<Powers>();
+    let frozen = ecs.read_storage::<Frozen>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let lights = ecs.read_storage::<LightSource>();
+    let intents = ecs.read_storage::<Intent>();
+    let chases = ecs.read_storage::<ChasePath>();
+
+    // entities cannot be saved, so whatever refers to one refers to its place in the list instead
+    let order: Vec<Entity> = entities.join().collect();
+    let index_of = |e: Entity| order.iter().position(|o| *o == e);
+
+    let saved = entities.join().map(|e| SavedEntity {
+        position: positions.get(e).cloned(),
+        direction: directions.get(e).cloned(),
+        renderable: renderables.get(e).map(SavedRenderable::from),
+        player: players.get(e).cloned(),
+        active: actives.get(e).cloned(),
+        monster: monsters.get(e).cloned(),
+        projectile: projectiles.get(e).cloned(),
+        solid: solids.get(e).cloned(),
+        combat_stats: stats.get(e).cloned(),
+        exit: exits.get(e).cloned(),
+        invulnerable: invulnerables.get(e).cloned(),
+        powers: powers.get(e).cloned(),
+        frozen: frozen.get(e).cloned(),
+        viewshed: viewsheds.get(e).cloned(),
+        light: lights.get(e).cloned(),
+        intent: intents.get(e).cloned(),
+        chase: chases.get(e).cloned(),
+        shot_by: projectiles.get(e).and_then(|p| p.owner).and_then(index_of),
+    }).collect();
+
+    let map = ecs.fetch::<Map>();
+    let data = SaveData {
+        version: VERSION,
+        seed: ecs.fetch::<Seed>().value,
+        rng_seed,
+        recording: recording.to_text(),
+        map: (*map).clone(),
+        lives: (*ecs.fetch::<Lives>()).clone(),
+        player_start: (*ecs.fetch::<PlayerStart>()).clone(),
+        entities: saved,
+    };
+
+    serde_json::to_string(&data).map_err(|e| e.to_string())
+}
+
+fn from_json(ecs: &mut World, text: &str) -> Result<Recording, String> {
+
+    let data: SaveData = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    if data.version != VERSION {
+        return Err(format!("save version {} is not supported, expected {}", data.version, VERSION));
+    }
+    let recording = Recording::from_text(&data.recording)?;
+
+    ecs.delete_all();
+    ecs.maintain();
+
+    let mut map = data.map;
+    map.clear_occupants(); // rebuilt by MapIndexing
+    ecs.insert(map);
+    ecs.insert(DistanceMaps::default()); // rebuilt at the next plan, the loaded intents still stand
+    ecs.insert(data.lives);
+    ecs.insert(data.player_start);
+    ecs.insert(Seed { value: data.seed });
+    ecs.insert(RandomNumberGenerator::seeded(data.rng_seed));
+
+    let mut owners: Vec<(Entity, usize)> = Vec::new();
+    let mut created: Vec<Entity> = Vec::new();
+    for saved in data.entities {
+        let mut builder = ecs.create_entity();
+        if let Some(c) = saved.position     { builder = builder.with(c); }
+        if let Some(c) = saved.direction    { builder = builder.with(c); }
+        if let Some(c) = saved.renderable   { builder = builder.with(c.to_renderable()); }
+        if let Some(c) = saved.player       { builder = builder.with(c); }
+        if let Some(c) = saved.active       { builder = builder.with(c); }
+        if let Some(c) = saved.monster      { builder = builder.with(c); }
+        if let Some(c) = saved.projectile   { builder = builder.with(c); }
+        if let Some(c) = saved.solid        { builder = builder.with(c); }
+        if let Some(c) = saved.combat_stats { builder = builder.with(c); }
+        if let Some(c) = saved.exit         { builder = builder.with(c); }
+        if let Some(c) = saved.invulnerable { builder = builder.with(c); }
+        if let Some(c) = saved.powers       { builder = builder.with(c); }
+        if let Some(c) = saved.frozen       { builder = builder.with(c); }
+        if let Some(c) = saved.light        { builder = builder.with(c); }
+        if let Some(c) = saved.intent       { builder = builder.with(c); }
+        if let Some(c) = saved.chase        { builder = builder.with(c); }
+        if let Some(mut c) = saved.viewshed {
+            c.dirty = true; // what was visible is not saved
+            builder = builder.with(c);
+        }
+        let e = builder.build();
+        if let Some(owner) = saved.shot_by {
+            owners.push((e, owner));
+        }
+        created.push(e);
+    }
+
+    // now that everyone exists again, shots can find who fired them
+    let mut projectiles = ecs.write_storage::<Projectile>();
+    for (e, owner) in owners {
+        if let Some(p) = projectiles.get_mut(e) {
+            p.owner = created.get(owner).copied();
+        }
+    }
+
+    Ok(recording)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod storage {
+    use std::fs;
+
+    pub fn read(name: &str) -> Result<String, String> {
+        fs::read_to_string(name).map_err(|e| format!("{}: {}", name, e))
+    }
+
+    pub fn write(name: &str, text: &str) -> Result<(), String> {
+        fs::write(name, text).map_err(|e| format!("{}: {}", name, e))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        // defined in wasm/index.html, backed by localStorage
+        fn storage_get(key: &str) -> Option<String>;
+        fn storage_set(key: &str, value: &str);
+    }
+
+    pub fn read(name: &str) -> Result<String, String> {
+        storage_get(name).ok_or(format!("{}: nothing saved", name))
+    }
+
+    pub fn write(name: &str, text: &str) -> Result<(), String> {
+        storage_set(name, text);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{components::Power, headless::Simulation, levels::Level, systems::{spawn_monster, Action}, util::Dir};
+
+    const ROOM: &str = "\
+#########
+#@......#
+#.####..#
+#.......#
+#########
+";
+
+    fn room() -> Simulation {
+        let mut sim = Simulation::with_level(1, &Level::parse("room", ROOM).unwrap());
+        spawn_monster(&mut sim.state.ecs, 7, 3, "chaser", Dir::Left);
+        spawn_monster(&mut sim.state.ecs, 6, 1, "shooter", Dir::Left);
+        sim
+    }
+
+    fn corridor() -> Simulation {
+        Simulation::with_level(1, &Level::parse("corridor", "######\n#@...#\n######\n").unwrap())
+    }
+
+    fn hp(sim: &Simulation) -> Vec<i32> {
+        let stats = sim.state.ecs.read_storage::<CombatStats>();
+        let players = sim.state.ecs.read_storage::<Player>();
+        (&stats, &players).join().map(|(s, _)| s.hp).collect()
+    }
+
+    /// play `before`, save, then play `after` both on and from the save. Both should end up the same
+    fn carry_on(make: fn() -> Simulation, before: &[Action], after: &[Action]) -> (Simulation, Simulation) {
+        let mut played = make();
+        played.run(before);
+        played.state.record(Action::Save);
+        let text = to_json(&mut played.state.ecs, &played.state.recording).unwrap();
+
+        let mut loaded = make();
+        let recording = from_json(&mut loaded.state.ecs, &text).unwrap();
+        loaded.state.resume(recording);
+        assert_eq!(loaded.to_text(), played.to_text());
+        assert_eq!(loaded.state.recording.to_text(), played.state.recording.to_text(), "rewound to the save");
+
+        for action in after {
+            played.step(*action);
+            loaded.step(*action);
+            assert_eq!(loaded.to_text(), played.to_text());
+            assert_eq!(hp(&loaded), hp(&played));
+        }
+        (played, loaded)
+    }
+
+    #[test]
+    fn loading_carries_on_like_playing_on() {
+        let before = [Action::Move(Dir::Down), Action::Move(Dir::Down), Action::Move(Dir::Up)];
+        let after = [Action::Move(Dir::Up), Action::Shoot, Action::Move(Dir::Right), Action::Move(Dir::Down)];
+        let (played, loaded) = carry_on(room, &before, &after);
+
+        let draw = |sim: &Simulation| sim.state.ecs.fetch_mut::<RandomNumberGenerator>().next_u64();
+        assert_eq!(draw(&loaded), draw(&played));
+    }
+
+    #[test]
+    fn a_bounce_in_flight_still_knows_its_shooter() {
+        let before = [Action::Move(Dir::Right), Action::Power(Power::Bounce)];
+        let mut sim = corridor();
+        sim.run(&before);
+        assert_eq!(sim.projectile_positions().len(), 1, "saved in flight");
+
+        // it bounces to and fro over the player until it runs out
+        let (played, _) = carry_on(corridor, &before, &[Action::Move(Dir::Up); 12]);
+        assert!(played.projectile_positions().is_empty());
+        assert_eq!(hp(&played), vec![3]);
+    }
+
+    #[test]
+    fn intents_and_chases_are_saved() {
+        let mut sim = room();
+        sim.run(&[Action::Move(Dir::Down), Action::Move(Dir::Down)]);
+        let text = to_json(&mut sim.state.ecs, &sim.state.recording).unwrap();
+
+        let mut loaded = room();
+        from_json(&mut loaded.state.ecs, &text).unwrap();
+        let count = |sim: &Simulation| (
+            sim.state.ecs.read_storage::<Intent>().join().count(),
+            sim.state.ecs.read_storage::<ChasePath>().join().count(),
+        );
+        assert_eq!(count(&loaded), count(&sim));
+        assert!(count(&sim).0 > 0);
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let mut sim = room();
+        let text = to_json(&mut sim.state.ecs, &sim.state.recording).unwrap();
+        let old = text.replacen(&format!("\"version\":{}", VERSION), "\"version\":1", 1);
+        match from_json(&mut sim.state.ecs, &old) {
+            Ok(_) => panic!("loaded an old save"),
+            Err(err) => assert!(err.contains("not supported"), "{}", err),
+        }
+    }
+}
@@ -1,66 +1,300 @@
 use rltk::RGB;
-use rltk::{GameState, Rltk};
+use rltk::{GameState, Rltk, VirtualKeyCode};
 use specs::World;
 use specs::prelude::*;
 
-use crate::components::Player;
+use rltk::RandomNumberGenerator;
+
+use crate::components::{CombatStats, Direction, Exit, Monster, Player, Active, Projectile, Solid, SufferDamage, WantsToMelee, Intent, Invulnerable, Powers, Frozen, Viewshed, LightSource};
 use crate::components::Position;
 use crate::components::Renderable;
 use crate::cons;
+use crate::saveload;
+use crate::menu;
+use crate::levels::Level;
 use crate::geo::Point;
-use crate::resources::{Camera, PlayerPos, Lives};
-use crate::systems::{MonsterAI, player_input, MapIndexing};
-use crate::{systems::{projectile_system, light_system}, map::Map};
+use crate::util::Dir;
+use crate::recording::{Recording, Replay};
+use crate::systems::{Action, apply_action, make_player, spawn_monsters};
+use crate::resources::{Camera, PlayerPos, PlayerStart, Lives, Seed, DistanceMaps};
+use crate::systems::{MonsterAI, act_on_intents, first_in_line, player_input, MapIndexing, MeleeCombatSystem, DamageSystem, InvulnerabilitySystem, VisibilitySystem, DistanceMapSystem, delete_the_dead};
+use crate::{systems::{projectile_system, light_system, Falloff}, map::Map};
+use crate::pathing::ChasePath;
+use crate::raws::Raws;
 
 #[derive(PartialEq, Copy, Clone)]
-pub enum RunState { AwaitingInput, PreRun, PlayerTurn, MonsterTurn }
+pub enum RunState { 
+    AwaitingInput, 
+    PreRun, 
+    PlayerTurn, 
+    MonsterTurn,
+    // the screens, all with a cursor
+    MainMenu { selection: usize },
+    LevelSelect { selection: usize },
+    Options { selection: usize },
+    Paused { selection: usize },
+    GameOver { selection: usize },
+    Victory { selection: usize },
+}
+
+impl RunState {
+
+    /// is a turn being played out, which does not need any input?
+    pub fn is_running(&self) -> bool {
+        matches!(self, RunState::PreRun | RunState::PlayerTurn | RunState::MonsterTurn)
+    }
+}
+
+pub struct Options {
+    pub show_seed: bool,
+    pub falloff: Falloff,
+}
 
 pub struct MyState {
     pub ecs: World,
-    pub runstate : RunState
+    pub runstate : RunState,
+    pub recording: Recording,
+    pub record_path: Option<String>, // write the recording here after every turn
+    pub replay: Option<Replay>,
+    pub next_seed: Option<u64>, // the seed of the next game started from the menu, random if None
+    pub options: Options,
 }
 
 impl GameState for MyState {
 
     fn tick(&mut self, ctx : &mut Rltk) {
         
+        ctx.cls();
+
+        self.runstate = match self.runstate {
+            RunState::MainMenu { selection } => menu::main_menu(self, ctx, selection),
+            RunState::LevelSelect { selection } => menu::level_select(self, ctx, selection),
+            RunState::Options { selection } => menu::options(self, ctx, selection),
+            RunState::Paused { selection } => {
+                self.render(ctx);
+                menu::paused(self, ctx, selection)
+            }
+            RunState::GameOver { selection } => {
+                self.render(ctx);
+                menu::game_over(self, ctx, selection)
+            }
+            RunState::Victory { selection } => {
+                self.render(ctx);
+                menu::victory(self, ctx, selection)
+            }
+            _ => {
+                let action = match self.runstate {
+                    RunState::AwaitingInput => {
+                        match ctx.key {
+                            Some(VirtualKeyCode::S) => self.save(),
+                            Some(VirtualKeyCode::L) => self.load(),
+                            _ => {}
+                        }
+                        player_input(self, ctx)
+                    },
+                    _ => None,
+                };
+                self.advance(action);
+                self.render(ctx);
+                if self.runstate == RunState::AwaitingInput && ctx.key == Some(VirtualKeyCode::Escape) {
+                    RunState::Paused { selection: 0 }
+                } else {
+                    self.runstate
+                }
+            }
+        };
+    }
+}
+impl MyState {
+
+    pub fn new(seed: u64) -> Self {
+        Self {
+            ecs: World::new(),
+            runstate: RunState::PreRun,
+            recording: Recording::new(seed),
+            record_path: None,
+            replay: None,
+            next_seed: None,
+            options: Options { show_seed: false, falloff: Falloff::Linear },
+        }   
+    }
+
+    /// throw away the current world and start a new game, on a level or in a maze
+    pub fn start(&mut self, level: Option<&Level>) -> RunState {
+        let seed = self.next_seed.take().unwrap_or_else(|| RandomNumberGenerator::new().next_u64());
+        rltk::console::log(&format!("seed: {}", seed));
+
+        let fresh = match level {
+            Some(level) => Self::new_level(seed, level),
+            None => Self::new_game(seed),
+        };
+        self.ecs = fresh.ecs;
+        self.recording = fresh.recording;
+        self.replay = None;
+        RunState::PreRun
+    }
+
+    /// a fresh game: the player in a random maze full of monsters
+    pub fn new_game(seed: u64) -> Self {
+        let mut gs = Self::new(seed);
+        gs.init_world(seed);
+
+        // create the player
+        make_player(&mut gs.ecs, 3, 3);
+        gs.ecs.insert(PlayerStart { pos: Point::new(3, 3) });
+
+        // create the map resource
+        let maze = {
+            let mut rng = gs.ecs.fetch_mut::<RandomNumberGenerator>();
+            Map::new_maze(cons::WIDTH, cons::HEIGHT, &mut rng)
+        };
+        spawn_monsters(&mut gs, &maze, 5);
+        gs.ecs.insert(maze);
+
+        gs
+    }
+
+    /// a fresh game on a hand-authored level
+    pub fn new_level(seed: u64, level: &Level) -> Self {
+        let mut gs = Self::new(seed);
+        gs.init_world(seed);
+        gs.recording.level = Some(level.name.clone());
+        level.spawn(&mut gs.ecs);
+        gs
+    }
+
+    /// register all components and create the resources every game needs
+    fn init_world(&mut self, seed: u64) {
+
+        // register all used components
+        self.ecs.register::<Position>();
+        self.ecs.register::<Direction>();
+        self.ecs.register::<Renderable>();
+        self.ecs.register::<Player>();
+        self.ecs.register::<Active>();
+        self.ecs.register::<Projectile>();
+        self.ecs.register::<Monster>();
+        self.ecs.register::<Solid>();
+        self.ecs.register::<CombatStats>();
+        self.ecs.register::<Exit>();
+        self.ecs.register::<WantsToMelee>();
+        self.ecs.register::<Intent>();
+        self.ecs.register::<SufferDamage>();
+        self.ecs.register::<Invulnerable>();
+        self.ecs.register::<Powers>();
+        self.ecs.register::<Frozen>();
+        self.ecs.register::<Viewshed>();
+        self.ecs.register::<LightSource>();
+        self.ecs.register::<ChasePath>();
+
+        self.ecs.insert(Seed { value: seed });
+        self.ecs.insert(RandomNumberGenerator::seeded(seed));
+
+        // create other resources
+        self.ecs.insert(Camera { offset: Point::new(0,0) });
+        self.ecs.insert(PlayerPos { pos: Point::new(0,0) });
+        self.ecs.insert(Lives { count: 3, max: 3 });
+        self.ecs.insert(DistanceMaps::default());
+        let raws = Raws::load();
+        self.recording.raws = Some(raws.hash);
+        self.ecs.insert(raws);
+    }
+
+    /// one step of the turn machine. `action` is only used while awaiting input.
+    /// this never touches the window, so it can run headless
+    pub fn advance(&mut self, action: Option<Action>) {
+
         match self.runstate {
             RunState::PreRun => {
-                self.run_systems(ctx);
+                self.run_systems();
                 self.runstate = RunState::AwaitingInput;
             }
             RunState::AwaitingInput => {
-                self.runstate = player_input(self, ctx);
+                if let Some(action) = action {
+                    apply_action(action, &mut self.ecs);
+                    self.record(action);
+                    if action.takes_turn() {
+                        self.runstate = RunState::PlayerTurn;
+                    } else {
+                        self.refresh(); // no turn to update the view, but switching bodies moves it
+                    }
+                }
             }
             RunState::PlayerTurn => {
-                self.run_systems(ctx);
-                self.runstate = RunState::MonsterTurn;
+                self.run_systems();
+                self.runstate = self.outcome().unwrap_or(RunState::MonsterTurn);
             }
             RunState::MonsterTurn => {
-                self.run_systems(ctx);
-                self.runstate = RunState::AwaitingInput;
+                self.run_systems();
+                self.runstate = self.outcome().unwrap_or(RunState::AwaitingInput);
             }
+            _ => {} // the screens need a window, see tick
         }
 
         self.update_resources();
+    }
 
-        // render 
-        ctx.cls();
-        self.render(ctx);
+    /// has the game been won or lost?
+    fn outcome(&self) -> Option<RunState> {
+        let players = self.ecs.read_storage::<Player>();
+        let exits = self.ecs.read_storage::<Exit>();
+        let positions = self.ecs.read_storage::<Position>();
+
+        if players.join().next().is_none() || self.ecs.fetch::<Lives>().count <= 0 {
+            return Some(RunState::GameOver { selection: 0 });
+        }
+        for (pos, _) in (&positions, &players).join() {
+            if (&positions, &exits).join().any(|(exit, _)| exit.x == pos.x && exit.y == pos.y) {
+                return Some(RunState::Victory { selection: 0 });
+            }
+        }
+        None
     }
-}
-impl MyState {
 
-    pub fn new() -> Self {
-        Self {
-            ecs: World::new(),
-            runstate: RunState::PreRun,
-        }   
+    pub fn save(&mut self) {
+        self.record(Action::Save);
+        match saveload::save_game(&mut self.ecs, &self.recording) {
+            Ok(()) => rltk::console::log("game saved"),
+            Err(err) => rltk::console::log(&format!("could not save: {}", err)),
+        }
+    }
+
+    /// the recording is rewound to the save, so the run can still be replayed
+    pub fn load(&mut self) {
+        match saveload::load_game(&mut self.ecs) {
+            Ok(recording) => {
+                self.resume(recording);
+                rltk::console::log("game loaded");
+            },
+            Err(err) => rltk::console::log(&format!("could not load: {}", err)),
+        }
+    }
+
+    /// carry on in a world that was just loaded, from the end of its recording
+    pub fn resume(&mut self, recording: Recording) {
+        self.recording = recording;
+        self.rewrite_recording();
+        self.replay = None;
+        self.refresh();
+        self.runstate = RunState::AwaitingInput;
+    }
+
+    pub fn record(&mut self, action: Action) {
+        self.recording.push(action);
+        self.rewrite_recording();
+    }
+
+    fn rewrite_recording(&self) {
+        if let Some(path) = &self.record_path {
+            if let Err(err) = self.recording.save(path) {
+                rltk::console::log(&format!("could not save recording: {}", err));
+            }
+        }
     }
 
     fn update_resources(&mut self) {
 
-        let players = self.ecs.read_storage::<Player>();
+        let actives = self.ecs.read_storage::<Active>();
         let positions = self.ecs.read_storage::<Position>();
 
         let mut cam = self.ecs.fetch_mut::<Camera>();
@@ -68,47 +302,140 @@ impl MyState {
 
         cam.offset = Point::new(cons::WIDTH as i32 / 2,cons::HEIGHT as i32 / 2);
 
-        for (pos, player) in (&positions, &players).join() {
+        for (pos, _active) in (&positions, &actives).join() {
             player_pos.pos.set(pos.x, pos.y);
             cam.offset.addn(-pos.x, -pos.y);
         }
     }
 
-    fn run_systems(&mut self, ctx : &mut Rltk) {
+    fn run_systems(&mut self) {
 
         projectile_system(self);
-        light_system(self);
 
         if self.runstate == RunState::MonsterTurn {
-            let mut mob = MonsterAI{};
-            mob.run_now(&self.ecs);
+            act_on_intents(&mut self.ecs);
+            let mut invulnerability = InvulnerabilitySystem{};
+            invulnerability.run_now(&self.ecs);
         }
 
+        let mut melee = MeleeCombatSystem{};
+        melee.run_now(&self.ecs);
+        let mut damage = DamageSystem{};
+        damage.run_now(&self.ecs);
+        self.ecs.maintain();
+        delete_the_dead(&mut self.ecs);
+
+        let mut mapindex = MapIndexing{};
+        mapindex.run_now(&self.ecs);
+        let mut visibility = VisibilitySystem{};
+        visibility.run_now(&self.ecs);
+        light_system(self); // after everyone moved, and with what the player sees now
+        self.ecs.maintain();
+
+        // the player gets to see what the monsters are up to before making a move
+        if matches!(self.runstate, RunState::PreRun | RunState::MonsterTurn) {
+            self.plan_monsters();
+        }
+    }
+
+    fn plan_monsters(&mut self) {
+        let mut distances = DistanceMapSystem{};
+        distances.run_now(&self.ecs);
+        let mut mob = MonsterAI{};
+        mob.run_now(&self.ecs);
+        self.ecs.maintain();
+    }
+
+    /// rebuild what is derived from the world (occupancy, sight, light) without playing a turn
+    fn refresh(&mut self) {
         let mut mapindex = MapIndexing{};
         mapindex.run_now(&self.ecs);
+        let mut visibility = VisibilitySystem{};
+        visibility.run_now(&self.ecs);
+        light_system(self);
         self.ecs.maintain();
+        self.update_resources(); // the monsters keep the plans they already made
     }
 
     fn render(&mut self, ctx : &mut Rltk) {
 
+        let entities = self.ecs.entities();
         let players = self.ecs.read_storage::<Player>();
+        let actives = self.ecs.read_storage::<Active>();
         let positions = self.ecs.read_storage::<Position>();
         let renderables = self.ecs.read_storage::<Renderable>();
+        let invulnerables = self.ecs.read_storage::<Invulnerable>();
+        let frozen = self.ecs.read_storage::<Frozen>();
+        let powers = self.ecs.read_storage::<Powers>();
+        let intents = self.ecs.read_storage::<Intent>();
         
         let map = self.ecs.fetch::<Map>();
         let cam = self.ecs.fetch::<Camera>();
         
         map.render(ctx, &cam.offset);
         
-        for (pos, render) in (&positions, &renderables).join() {
+        for (e, pos, render, inv, frozen) in (&entities, &positions, &renderables, invulnerables.maybe(), frozen.maybe()).join() {
             let light = map.get_light(pos.x, pos.y).unwrap_or(0.0);
-            if light < 0.1 { continue };
+            if light < 0.1 || !map.is_visible(pos.x, pos.y) { continue };
+            let idle = players.contains(e) && !actives.contains(e);
+            let fg = match (inv, frozen) {
+                (Some(_), _) => RGB::named(rltk::GREY),
+                (_, Some(_)) => RGB::named(rltk::CYAN),
+                _ if idle => RGB::named(rltk::ORANGE),
+                _ => render.foreground,
+            };
+            // the background takes on the light as is, the glyph only its color, so it stays readable
+            let tint = map.get_light_color(pos.x, pos.y).unwrap_or(RGB::named(rltk::BLACK));
+            let hue = tint * (1.0 / light);
             ctx.set(pos.x + cam.offset.x, pos.y + cam.offset.y, 
-                render.foreground, 
-                RGB::lerp(&RGB::named(rltk::BLACK), render.background, light), 
+                fg * hue, 
+                render.background * tint, 
                 render.glyph);
         }
 
+        // what the monsters in sight are about to do, so the player can plan around it
+        for (pos, render, intent) in (&positions, &renderables, &intents).join() {
+            let light = map.get_light(pos.x, pos.y).unwrap_or(0.0);
+            if light < 0.1 || !map.is_visible(pos.x, pos.y) { continue };
+            let at = pos.to_point();
+            let screen = |p: Point| (p.x + cam.offset.x, p.y + cam.offset.y);
+            match *intent {
+                Intent::Wait | Intent::Turn(_) => {}
+                Intent::Move(d) => {
+                    let to = at.add(&d.vector());
+                    if map.is_occupied(to.x, to.y) { continue }
+                    let arrow = match d {
+                        Dir::Left  => '←',
+                        Dir::Right => '→',
+                        Dir::Up    => '↑',
+                        Dir::Down  => '↓',
+                    };
+                    let tint = map.get_light_color(to.x, to.y).unwrap_or(RGB::named(rltk::BLACK));
+                    let (x, y) = screen(to);
+                    ctx.set(x, y, render.foreground, cons::RGB_BACKGROUND * tint, rltk::to_cp437(arrow));
+                }
+                Intent::Attack(d) => {
+                    let (x, y) = screen(at.add(&d.vector()));
+                    ctx.set_bg(x, y, cons::RGB_DANGER);
+                }
+                Intent::Shoot(d) => {
+                    // the line of fire, up to whatever it hits first
+                    let end = first_in_line(&map, at, d);
+                    let mut p = at;
+                    for _ in 0..cons::POWER_RANGE {
+                        p = p.add(&d.vector());
+                        let (x, y) = screen(p);
+                        ctx.set_bg(x, y, cons::RGB_DANGER);
+                        if Some(p) == end { break }
+                    }
+                }
+                Intent::Push(d) => {
+                    let (x, y) = screen(at.add(&d.vector()));
+                    ctx.set_bg(x, y, cons::RGB_SHOVE);
+                }
+            }
+        }
+
         // UI
         let lives = self.ecs.fetch::<Lives>();
         for i in 0..lives.max { 
@@ -130,6 +457,17 @@ impl MyState {
             )
          }
 
+        // the powers, with the key that uses them
+        for (_, powers) in (&actives, &powers).join() {
+            for (i, power) in powers.list.iter().enumerate() {
+                ctx.print(1, cons::HEIGHT - 2 - i, format!("{} {}", i + 1, power.name()));
+            }
+        }
+
+        if self.options.show_seed {
+            ctx.print(1, 1, format!("seed {}", self.ecs.fetch::<Seed>().value));
+        }
+
 
     }
 }
\ No newline at end of file
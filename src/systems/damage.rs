@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+
+use specs::prelude::*;
+use rltk::console;
+
+use crate::{components::{CombatStats, SufferDamage, Player, Active, Position, Invulnerable, Viewshed}, map::{Map, Tile}, resources::{Lives, PlayerStart}, geo::Point, cons};
+
+pub struct DamageSystem {}
+
+impl<'a> System<'a> for DamageSystem {
+    type SystemData = ( ReadExpect<'a, Map>,
+                        WriteStorage<'a, CombatStats>,
+                        WriteStorage<'a, SufferDamage>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Invulnerable>
+                    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (map, mut stats, mut damages, positions, invulnerables) = data;
+
+        for (stats, damage, _) in (&mut stats, &damages, !&invulnerables).join() {
+            stats.hp -= damage.amount.iter().sum::<i32>();
+        }
+
+        // nobody survives the abyss, not even the invulnerable
+        for (stats, pos) in (&mut stats, &positions).join() {
+            if map.get_tile(pos.x, pos.y) == Some(Tile::Empty) {
+                stats.hp = 0;
+            }
+        }
+
+        damages.clear();
+    }
+}
+
+/// count down the turns of invulnerability, once per round
+pub struct InvulnerabilitySystem {}
+
+impl<'a> System<'a> for InvulnerabilitySystem {
+    type SystemData = ( Entities<'a>,
+                        WriteStorage<'a, Invulnerable>
+                    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (entities, mut invulnerables) = data;
+
+        let mut expired: Vec<Entity> = Vec::new();
+        for (e, inv) in (&entities, &mut invulnerables).join() {
+            inv.turns -= 1;
+            if inv.turns <= 0 {
+                expired.push(e);
+            }
+        }
+        for e in expired {
+            invulnerables.remove(e);
+        }
+    }
+}
+
+/// remove everything that ran out of hp. A player body is simply lost as long as another one lives on.
+/// Losing the last body costs a life instead, and it is only removed when the last life is gone
+pub fn delete_the_dead(ecs: &mut World) {
+    let mut dead: Vec<Entity> = Vec::new();
+    let mut dead_players: Vec<Entity> = Vec::new();
+    let mut survivors: Vec<Entity> = Vec::new();
+    {
+        let entities = ecs.entities();
+        let stats = ecs.read_storage::<CombatStats>();
+        let players = ecs.read_storage::<Player>();
+        for (e, stats) in (&entities, &stats).join() {
+            if stats.hp < 1 {
+                if players.contains(e) {
+                    dead_players.push(e);
+                } else {
+                    dead.push(e);
+                }
+            } else if players.contains(e) {
+                survivors.push(e);
+            }
+        }
+    }
+
+    if !dead_players.is_empty() {
+        if survivors.is_empty() {
+            // the whole party is gone. bring back the body we were controlling
+            let active = {
+                let actives = ecs.read_storage::<Active>();
+                dead_players.iter().position(|e| actives.contains(*e)).unwrap_or(0)
+            };
+            let last = dead_players.remove(active);
+            if !respawn(ecs, last) {
+                dead.push(last);
+            }
+        } else {
+            // lost the body we were controlling? take over another one
+            let mut actives = ecs.write_storage::<Active>();
+            if dead_players.iter().any(|e| actives.contains(*e)) {
+                actives.insert(survivors[0], Active {}).expect("unable to switch bodies");
+            }
+        }
+        dead.extend(dead_players);
+    }
+
+    for victim in dead {
+        ecs.delete_entity(victim).expect("unable to delete the dead");
+    }
+}
+
+/// spend a life to bring the player back at the start. false if there were no lives left
+fn respawn(ecs: &mut World, player: Entity) -> bool {
+    let mut lives = ecs.fetch_mut::<Lives>();
+    lives.count = i32::max(0, lives.count - 1);
+    if lives.count == 0 {
+        console::log("you died. game over");
+        return false;
+    }
+    console::log(&format!("you died. {} lives left", lives.count));
+
+    let mut map = ecs.fetch_mut::<Map>();
+    let start = nearest_free(&map, ecs.fetch::<PlayerStart>().pos, player);
+    let mut positions = ecs.write_storage::<Position>();
+    let mut stats = ecs.write_storage::<CombatStats>();
+    let mut invulnerables = ecs.write_storage::<Invulnerable>();
+    let mut actives = ecs.write_storage::<Active>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+    if let Some(pos) = positions.get_mut(player) {
+        map.remove_occupant(pos.x, pos.y, player);
+        pos.x = start.x;
+        pos.y = start.y;
+        map.add_occupant(pos.x, pos.y, player);
+    }
+    if let Some(viewshed) = viewsheds.get_mut(player) {
+        viewshed.dirty = true;
+    }
+    if let Some(stats) = stats.get_mut(player) {
+        stats.hp = stats.max_hp;
+    }
+    invulnerables
+        .insert(player, Invulnerable { turns: cons::INVULNERABLE_TURNS })
+        .expect("unable to insert invulnerability");
+    actives.insert(player, Active {}).expect("unable to insert active");
+    true
+}
+
+/// the start, or the closest floor to it where nobody but `body` is standing
+fn nearest_free(map: &Map, start: Point, body: Entity) -> Point {
+    let free = |id: usize| map.occupants[id].iter().all(|o| *o == body);
+    let first = match map.to_index(start.x, start.y) {
+        Some(id) => id,
+        None => return start,
+    };
+    let mut seen = vec![false; map.size()];
+    let mut open = VecDeque::from([first]);
+    seen[first] = true;
+    while let Some(id) = open.pop_front() {
+        if map.tiles[id] == Tile::Floor && free(id) {
+            let (x, y) = map.to_coord(id);
+            return Point::new(x, y);
+        }
+        for next in map.exits(id) {
+            if !seen[next] {
+                seen[next] = true;
+                open.push_back(next);
+            }
+        }
+    }
+    start // nowhere to go, share the cell rather than not come back at all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{headless::Simulation, levels::Level, systems::{spawn_monster, Action, MapIndexing}, util::Dir};
+
+    #[test]
+    fn respawning_next_to_whoever_took_the_start() {
+        let level = Level::parse("hall", "#######\n#@....#\n#######\n").unwrap();
+        let mut sim = Simulation::with_level(1, &level);
+        sim.run(&[Action::Move(Dir::Right); 3]);
+        spawn_monster(&mut sim.state.ecs, 1, 1, "patroller", Dir::Up);
+        MapIndexing {}.run_now(&sim.state.ecs);
+
+        let player = sim.state.ecs.fetch::<Map>().get_occupants(4, 1)[0];
+        sim.state.ecs.write_storage::<CombatStats>().get_mut(player).unwrap().hp = 0;
+        delete_the_dead(&mut sim.state.ecs);
+
+        assert_eq!(sim.player_positions(), vec![(2, 1)]);
+        let map = sim.state.ecs.fetch::<Map>();
+        assert_eq!(map.get_occupants(2, 1), &[player]);
+        assert!(map.get_occupants(4, 1).is_empty());
+        assert_eq!(map.get_occupants(1, 1).len(), 1, "only the monster on the start");
+    }
+}
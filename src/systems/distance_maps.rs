@@ -0,0 +1,28 @@
+use specs::prelude::*;
+
+use crate::{components::{Position, Player}, resources::DistanceMaps, map::Map, geo::Point, pathing::DistanceMap};
+
+/// rebuild the shared flow fields when the players moved, or the terrain changed
+pub struct DistanceMapSystem {}
+
+impl<'a> System<'a> for DistanceMapSystem {
+    type SystemData = ( ReadExpect<'a, Map>,
+                        WriteExpect<'a, DistanceMaps>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Player>
+                    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (map, mut fields, positions, players) = data;
+
+        let player_cells: Vec<Point> = (&positions, &players).join().map(|(pos, _)| pos.to_point()).collect();
+        let terrain_changed = fields.revision != Some(map.revision);
+
+        if terrain_changed || fields.players != player_cells {
+            fields.to_players = DistanceMap::new(&map, &player_cells);
+            fields.from_players = fields.to_players.fleeing(&map);
+            fields.players = player_cells;
+        }
+        fields.revision = Some(map.revision);
+    }
+}
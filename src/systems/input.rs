@@ -3,16 +3,21 @@ use std::cmp::{min, max};
 use rltk::{RGB, VirtualKeyCode, Rltk};
 use specs::prelude::*;
 
-use crate::{util::Dir, components::{Position, Player, Renderable, Projectile, Direction}, map::Map, cons, state::{MyState, RunState}};
+use crate::{util::Dir, geo::Point, components::{Position, Active, Player, Renderable, Projectile, ProjectileKind, Direction, CombatStats, WantsToMelee, Power, Powers, Viewshed, LightSource}, map::Map, cons, state::{MyState, RunState}, saveload, systems::{use_power, switch_body}};
 
 
 fn try_move_player(dir: Dir, ecs: &mut World) {
     
     let mut positions = ecs.write_storage::<Position>();
-    let mut players = ecs.write_storage::<Player>();
+    let actives = ecs.read_storage::<Active>();
     let mut rends = ecs.write_storage::<Renderable>();
     let mut dirs = ecs.write_storage::<Direction>();
-    let map = ecs.fetch_mut::<Map>();
+    let stats = ecs.read_storage::<CombatStats>();
+    let players = ecs.read_storage::<Player>();
+    let mut wants_melee = ecs.write_storage::<WantsToMelee>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+    let entities = ecs.entities();
+    let mut map = ecs.fetch_mut::<Map>();
 
     let char = match dir {
         Dir::Left  => '◄', // < ◄
@@ -23,65 +28,159 @@ fn try_move_player(dir: Dir, ecs: &mut World) {
 
     let (dx, dy) = dir.xy();
 
-    for (_player, pos, rends, d) in (&mut players, &mut positions, &mut rends, &mut dirs).join() {
+    for (e, _active, pos, rends, d) in (&entities, &actives, &mut positions, &mut rends, &mut dirs).join() {
 
         // fix dir
         d.dir = dir;
         rends.glyph = rltk::to_cp437(char);
+        if let Some(viewshed) = viewsheds.get_mut(e) {
+            viewshed.dirty = true;
+        }
         
         let (nx, ny) = (pos.x + dx, pos.y + dy);
 
+        // bump into something that can be hurt? attack it instead of moving. Our own bodies just stand in the way
+        let target = map.get_occupants(nx, ny).iter().find(|o| stats.contains(**o) && !players.contains(**o)).copied();
+        if let Some(target) = target {
+            wants_melee.insert(e, WantsToMelee { target }).expect("unable to insert attack");
+            continue;
+        }
+
         // actually move (but never out of screen)
         if map.is_free(nx, ny) {
+            map.remove_occupant(pos.x, pos.y, e);
             pos.x = min((cons::WIDTH - 1) as i32 , max(0, nx));
             pos.y = min((cons::HEIGHT - 1) as i32, max(0, ny));
+            map.add_occupant(pos.x, pos.y, e);
         }
     }
 }
 
-fn get_player( ecs: &mut specs::World) -> (Position, Dir) {
-    let mut positions = ecs.write_storage::<Position>();
-    let mut players = ecs.write_storage::<Player>();
-    let mut directions = ecs.write_storage::<Direction>();
-
-    let mut position = Position::new(0,0);
-    let mut direction = Dir::Left;
-    for (_player, pos, dir) in (&mut players, &mut positions, &mut directions).join() {
-        position.x = pos.x;
-        position.y = pos.y;
-        direction = dir.dir.clone();
-    }
-    return (position, direction);
+/// the body that is being controlled, where it stands and where it looks
+pub fn get_player(ecs: &World) -> Option<(Entity, Point, Dir)> {
+    let entities = ecs.entities();
+    let actives = ecs.read_storage::<Active>();
+    let positions = ecs.read_storage::<Position>();
+    let directions = ecs.read_storage::<Direction>();
+    (&entities, &actives, &positions, &directions).join()
+        .next()
+        .map(|(e, _, pos, dir)| (e, pos.to_point(), dir.dir))
 }
 
-fn try_player_shoot(ecs: &mut World) {
-    let (pos, dir) = get_player(ecs);
-    // let (dx, dy) = dir_to_xy(dir);
+/// nothing happens without a body to shoot from
+pub fn try_player_shoot(ecs: &mut World, kind: ProjectileKind) {
+    let (player, pos, dir) = match get_player(ecs) {
+        Some(player) => player,
+        None => return,
+    };
+    let color = match kind {
+        ProjectileKind::Shot => rltk::BLUE2,
+        ProjectileKind::Bouncing { .. } => rltk::MAGENTA,
+    };
+    spawn_projectile(ecs, pos, dir, kind, true, color, Some(player));
+}
 
+/// a projectile leaving `from`, glowing in its color. `knockback` pushes whatever it hits a step further
+pub fn spawn_projectile(ecs: &mut World, from: Point, dir: Dir, kind: ProjectileKind, knockback: bool, color: (u8, u8, u8), owner: Option<Entity>) {
+    let lifetime = match kind {
+        ProjectileKind::Shot => 10,
+        ProjectileKind::Bouncing { .. } => cons::BOUNCE_LIFETIME,
+    };
+    let glow = RGB::named(color);
     ecs
         .create_entity()
-        .with(Position::new(pos.x, pos.y))
-        .with(Projectile {dir, lifetime: 10})
+        .with(Position::new(from.x, from.y))
+        .with(Projectile {kind, dir, lifetime, power: 1, knockback, strength: cons::PUSH_STRENGTH, owner})
         .with(Renderable::new(
             rltk::to_cp437('◙'), 
-            RGB::named(rltk::BLUE2), 
+            glow, 
             RGB::named(rltk::BLACK)))
+        .with(LightSource::new(3.0, 0.6, (glow.r, glow.g, glow.b)))
         .build();
 }
 
-pub fn player_input(gs: &mut MyState, ctx: &mut Rltk) -> RunState {
+/// everything the player can do in a turn. These are recorded and replayed as is
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Action {
+    Move(Dir),
+    Shoot,
+    Power(Power),
+    Switch, // control the next body
+    Save,   // saving reseeds the rng, so it is part of the run
+}
+
+impl Action {
+
+    /// switching bodies and saving are free, everything else ends the turn
+    pub fn takes_turn(&self) -> bool {
+        !matches!(self, Action::Switch | Action::Save)
+    }
+}
+
+/// translate the pressed key into an action, if any. The number keys use the powers of the active body
+pub fn read_action(ctx: &Rltk, ecs: &World) -> Option<Action> {
+    let slot = match ctx.key? {
+        VirtualKeyCode::Left  => return Some(Action::Move(Dir::Left)),
+        VirtualKeyCode::Right => return Some(Action::Move(Dir::Right)),
+        VirtualKeyCode::Up    => return Some(Action::Move(Dir::Up)),
+        VirtualKeyCode::Down  => return Some(Action::Move(Dir::Down)),
+        VirtualKeyCode::Space => return Some(Action::Shoot),
+        VirtualKeyCode::Tab   => return Some(Action::Switch),
+        VirtualKeyCode::Key1  => 0,
+        VirtualKeyCode::Key2  => 1,
+        VirtualKeyCode::Key3  => 2,
+        VirtualKeyCode::Key4  => 3,
+        VirtualKeyCode::Key5  => 4,
+        VirtualKeyCode::Key6  => 5,
+        _ => return None,
+    };
+    let actives = ecs.read_storage::<Active>();
+    let powers = ecs.read_storage::<Powers>();
+    let (_, powers) = (&actives, &powers).join().next()?;
+    powers.list.get(slot).map(|power| Action::Power(*power))
+}
+
+pub fn apply_action(action: Action, ecs: &mut World) {
+    match action {
+        Action::Move(dir) => try_move_player(dir, ecs),
+        Action::Shoot => try_player_shoot(ecs, ProjectileKind::Shot),
+        Action::Power(power) => use_power(power, ecs),
+        Action::Switch => switch_body(ecs),
+        Action::Save => { saveload::reseed(ecs); } // the file is only written by MyState::save
+    }
+}
+
+/// the action of this turn: from the replay if one is running, otherwise from the keyboard
+pub fn player_input(gs: &mut MyState, ctx: &mut Rltk) -> Option<Action> {
     
-    // Player movement
-    match ctx.key {
-        None => { return RunState::AwaitingInput } // Nothing happened, try again
-        Some(key) => match key {
-            VirtualKeyCode::Left  => try_move_player(Dir::Left, &mut gs.ecs),
-            VirtualKeyCode::Right => try_move_player(Dir::Right, &mut gs.ecs),
-            VirtualKeyCode::Up    => try_move_player(Dir::Up, &mut gs.ecs),
-            VirtualKeyCode::Down  => try_move_player(Dir::Down, &mut gs.ecs),
-            VirtualKeyCode::Space  => try_player_shoot(&mut gs.ecs),
-            _ => { return RunState::AwaitingInput }
-        },
+    // a replay takes over the keyboard until it runs out
+    let action = match gs.replay.as_mut() {
+        Some(replay) => replay.next(),
+        None => read_action(ctx, &gs.ecs),
+    };
+    if gs.replay.as_ref().map_or(false, |r| r.is_done()) {
+        rltk::console::log("replay finished");
+        gs.replay = None;
     }
-    RunState::PlayerTurn // return this after a succesfull move
-}
\ No newline at end of file
+    action
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{headless::Simulation, levels::Level};
+
+    #[test]
+    fn bumping_into_a_clone_does_not_hurt_it() {
+        let level = Level::parse("hall", "######\n#@...#\n######\n").unwrap();
+        let mut sim = Simulation::with_level(1, &level);
+        sim.run(&[Action::Move(Dir::Right), Action::Power(Power::Clone)]);
+        assert_eq!(sim.player_positions(), vec![(2, 1), (3, 1)]);
+
+        sim.run(&[Action::Move(Dir::Right); 3]);
+        assert_eq!(sim.player_positions(), vec![(2, 1), (3, 1)], "the clone blocks the way");
+        let stats = sim.state.ecs.read_storage::<CombatStats>();
+        let players = sim.state.ecs.read_storage::<Player>();
+        assert!((&stats, &players).join().all(|(s, _)| s.hp == s.max_hp));
+    }
+}
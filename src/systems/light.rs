@@ -1,50 +1,80 @@
-use crate::{components::{Position, Player}, map::{Map}, state::MyState, geo::{Circle, Point, Line}};
+use crate::{components::{Position, Direction, LightSource, LightShape}, map::{Map}, state::MyState, geo::{Circle, Point}, util};
+use rltk::RandomNumberGenerator;
 use specs::prelude::*;
 
-pub fn light_system(state: &mut MyState) {
+/// how light fades with the distance to its source
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Falloff {
+    Linear,
+    Smooth, // stays bright for a while, then quickly fades out
+    InverseSquare, // like real light, bright up close, with a long dim tail
+}
+
+impl Falloff {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Falloff::Linear => "linear",
+            Falloff::Smooth => "smooth",
+            Falloff::InverseSquare => "inverse square",
+        }
+    }
+
+    pub fn next(&self) -> Falloff {
+        match self {
+            Falloff::Linear => Falloff::Smooth,
+            Falloff::Smooth => Falloff::InverseSquare,
+            Falloff::InverseSquare => Falloff::Linear,
+        }
+    }
 
-    const RADIUS: f32 = 9.5;
+    /// the brightness at `distance` from a source reaching up to `radius`, between 1 and 0
+    pub fn apply(&self, distance: f32, radius: f32) -> f32 {
+        let t = (distance / radius).clamp(0.0, 1.0);
+        match self {
+            Falloff::Linear => 1.0 - t,
+            Falloff::Smooth => 1.0 - util::smooth(t),
+            // scaled so it is about a tenth at the radius, and cut off there
+            Falloff::InverseSquare => if t < 1.0 { 1.0 / (1.0 + 9.0 * t * t) } else { 0.0 },
+        }
+    }
+}
+
+pub fn light_system(state: &mut MyState) {
 
+    let falloff = state.options.falloff;
+    let turn = state.recording.actions.len() as u64;
     let positions = state.ecs.read_storage::<Position>();
-    let players = state.ecs.read_storage::<Player>();
+    let sources = state.ecs.read_storage::<LightSource>();
+    let directions = state.ecs.read_storage::<Direction>();
 
     let mut map = state.ecs.fetch_mut::<Map>();
 
-    // map.light.fill(0.5);
-
-    // its possible to do this way easier
-    for (pos, _player) in (&positions, &players).join() {
-        
-        let c = Circle::new(Point::new(pos.x, pos.y), RADIUS);
-        // let points = c.to_grid_arc(_player.dir.rad() - cons::HALF_PI* 0.5, _player.dir.rad() + cons::HALF_PI * 0.5);
-        let points = c.to_grid_edge();
-        for p in points {
-            let line = Line::new(c.center.clone(), p);
-            let lps = line.to_grid();
-            // let mut scale = 0.0;
-            for (i, l) in lps.iter().enumerate() {
-                let scale = i as f32 / lps.len() as f32;
-                
-                let f = 1.0 - scale; 
-                // if scale > dropoff {
-                //     f = 1.0 - (scale-dropoff) * (1.0 / dropoff); 
-                // }
-                map.set_light(l.x, l.y, f);
-                // if !map.is_free(l.x, l.y) { break; };
-            }
-            // map.light.set(line.to.x, line.to.y, 0.2);
+    // light only reaches what the source can see: walls are lit on the side facing it, and cast a shadow
+    map.darken_all();
+    for (pos, source, dir) in (&positions, &sources, directions.maybe()).join() {
+
+        // flickering follows the turn, not the world's rng, so it never changes how a run plays out
+        let mut intensity = source.intensity;
+        if let Some(flicker) = source.flicker {
+            let mut rng = RandomNumberGenerator::seeded(turn * 7919 + (pos.x * 31 + pos.y) as u64);
+            intensity *= 1.0 - flicker * rng.range(0, 100) as f32 / 100.0;
         }
 
-        // for p in circle.to_grid_arc(dir.rad() - range, dir.rad() + range) {
-        //     let line = Line::new(circle.center.clone(), p);
-        //     for l in line.to_grid() {
-        //         map.
-        //     }
-        //     spawn(ecs, line.to.x, line.to.y, 'A');
-        // }
-
-        // 1 build arc
-        // 2 build lines between arc 
-        // 3 make all those points visible
+        let c = Circle::new(pos.to_point(), source.radius);
+        let in_reach = |p: &Point| match (source.shape, dir) {
+            (LightShape::Cone { arc }, Some(dir)) => {
+                let facing = dir.dir.rad();
+                c.contains(p) && c.in_arc(p, facing - arc / 2.0, facing + arc / 2.0)
+            }
+            _ => c.contains(p),
+        };
+        for id in map.cells_in_sight(c.center, source.radius.ceil() as i32, in_reach) {
+            let (x, y) = map.to_coord(id);
+            let (dx, dy) = ((x - pos.x) as f32, (y - pos.y) as f32);
+            let f = falloff.apply((dx * dx + dy * dy).sqrt(), source.radius) * intensity;
+            map.add_light(x, y, (source.color.0 * f, source.color.1 * f, source.color.2 * f));
+        }
     }
-}
\ No newline at end of file
+    map.reveal_seen();
+}
@@ -9,18 +9,19 @@ pub struct MapIndexing {}
 
 impl<'a> System<'a> for MapIndexing {
     type SystemData = ( WriteExpect<'a, Map>,
+                        Entities<'a>,
                         ReadStorage<'a, Position>,
                         ReadStorage<'a, Solid>
                     );
 
     fn run(&mut self, data : Self::SystemData) {
-        let (mut map, posses, solids) = data;
+        let (mut map, entities, posses, solids) = data;
         
-        map.clear_all_entities();
+        map.clear_occupants();
 
-        // basic AI: move around, dont bump into things
-        for (pos, _) in (&posses, &solids).join() {
-            map.apply_entity(pos.x, pos.y);
+        // register every solid thing in the occupancy layer, terrain stays untouched
+        for (e, pos, _) in (&entities, &posses, &solids).join() {
+            map.add_occupant(pos.x, pos.y, e);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,37 @@
+use specs::prelude::*;
+use rltk::console;
+
+use crate::components::{CombatStats, WantsToMelee, SufferDamage, Player};
+
+pub struct MeleeCombatSystem {}
+
+impl<'a> System<'a> for MeleeCombatSystem {
+    type SystemData = ( Entities<'a>,
+                        WriteStorage<'a, WantsToMelee>,
+                        ReadStorage<'a, CombatStats>,
+                        WriteStorage<'a, SufferDamage>,
+                        ReadStorage<'a, Player>
+                    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (entities, mut wants_melee, stats, mut damages, players) = data;
+
+        for (e, wants, attacker) in (&entities, &wants_melee, &stats).join() {
+            if attacker.hp <= 0 { continue };
+            let target = match stats.get(wants.target) {
+                Some(target) => target,
+                None => continue,
+            };
+            if target.hp <= 0 { continue };
+
+            let damage = attacker.damage_to(target);
+            let who = if players.contains(e) { "player" } else { "monster" };
+            console::log(&format!("{} hits for {} damage", who, damage));
+            if damage > 0 {
+                SufferDamage::new_damage(&mut damages, wants.target, damage);
+            }
+        }
+
+        wants_melee.clear();
+    }
+}
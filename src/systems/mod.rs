@@ -4,6 +4,12 @@ mod spawner;
 mod monster_ai;
 mod input;
 mod map_indexing;
+mod melee_combat;
+mod damage;
+mod push;
+mod powers;
+mod viewshed;
+mod distance_maps;
 
 pub use light::*;
 pub use projectile::*;
@@ -11,4 +17,10 @@ pub use monster_ai::*;
 pub use spawner::*;
 pub use input::*;
 pub use map_indexing::*;
+pub use melee_combat::*;
+pub use damage::*;
+pub use push::*;
+pub use powers::*;
+pub use viewshed::*;
+pub use distance_maps::*;
 
@@ -3,58 +3,354 @@ use std::ptr::swap;
 use specs::prelude::*;
 use rltk::{console, RandomNumberGenerator};
 
-use crate::{components::{Position, Monster, Direction, Player, Renderable}, resources::PlayerPos, map::{Map, Tile}, util::Dir};
+use crate::{components::{Position, Monster, AiState, Intent, Direction, Player, Renderable, WantsToMelee, Frozen, Viewshed, CombatStats, ProjectileKind}, resources::{PlayerPos, DistanceMaps}, map::{Map, Tile}, geo::Point, util::Dir, pathing::ChasePath, systems::{first_in_line, spawn_projectile, apply_push}, cons};
 
+/// plan what every monster does next turn. Nothing moves yet, see `act_on_intents`
 pub struct MonsterAI {}
 
 impl<'a> System<'a> for MonsterAI {
     type SystemData = ( ReadExpect<'a, PlayerPos>,
+                        ReadExpect<'a, DistanceMaps>,
                         ReadExpect<'a, Map>,
+                        Entities<'a>,
                         WriteExpect<'a, RandomNumberGenerator>,
-                        WriteStorage<'a, Position>,
-                        ReadStorage<'a, Monster>,
+                        ReadStorage<'a, Position>,
+                        WriteStorage<'a, Monster>,
                         WriteStorage<'a, Renderable>,
-                        WriteStorage<'a, Direction>);
+                        ReadStorage<'a, Direction>,
+                        ReadStorage<'a, Player>,
+                        ReadStorage<'a, CombatStats>,
+                        WriteStorage<'a, Intent>,
+                        ReadStorage<'a, Frozen>,
+                        ReadStorage<'a, Viewshed>,
+                        WriteStorage<'a, ChasePath>);
 
     fn run(&mut self, data : Self::SystemData) {
-        let (_pos, map, mut rng, mut poss, mobs, mut rends, mut dirs) = data;
+        let (_pos, fields, map, entities, mut rng, poss, mut mobs, mut rends, dirs, players, stats, mut intents, frozen, viewsheds, mut chases) = data;
         
-        // basic AI: move around, dont bump into things
-        for (_mob, pos, dir) in (&mobs, &mut poss, &mut dirs).join() {
-            let vector = dir.dir.vector();
+        let targets: Vec<Point> = (&players, &poss).join().map(|(_, pos)| pos.to_point()).collect();
+        let has_player = |p: Point| map.get_occupants_at(p).iter().any(|o| players.contains(*o));
+
+        // every monster sees, picks its state, and decides on an intent
+        for (e, mob, pos, dir) in (&entities, &mut mobs, &poss, &dirs).join() {
             let pt = pos.to_point();
-            let next = pt.add(&vector);
-            if map.is_free_at(next) {
-                pos.x += vector.x;    
-                pos.y += vector.y;
-            } else {
-                // change direction semi randomly
-                let mut left = dir.dir.next();
-                let mut right = dir.dir.prev();
-                if rng.range(0, 2) > 0 {
-                    let temp = left;
-                    left = right;
-                    right = temp;
-                }
-
-                if map.is_free_at(pt.add(&left.vector())) {
-                    dir.dir = left;
-                } else if map.is_free_at(pt.add(&right.vector())) {
-                    dir.dir = right;
-                } else {
-                    dir.dir = left;
+            let facing = dir.dir;
+
+            // frozen, or hanging above the abyss about to fall? nothing to do
+            if frozen.contains(e) || map.get_tile(pt.x, pt.y) == Some(Tile::Empty) {
+                intents.insert(e, Intent::Wait).expect("unable to insert intent");
+                continue;
+            }
+
+            let spotted = viewsheds.get(e).and_then(|viewshed| {
+                targets.iter().find(|t| map.to_index(t.x, t.y).is_some_and(|id| viewshed.can_see(id))).copied()
+            });
+            let hurt = stats.get(e).is_some_and(|s| s.hp <= mob.behavior.flees_at);
+            if let (Some(target), true) = (spotted, mob.behavior.hunts) {
+                match chases.get_mut(e) {
+                    Some(chase) => chase.retarget(target),
+                    None => { chases.insert(e, ChasePath::new(target)).expect("unable to insert chase"); }
+                }
+            }
+            mob.unseen = if spotted.is_some() { 0 } else { mob.unseen.saturating_add(1) };
+            mob.state = next_state(mob, spotted.is_some(), hurt, chases.contains(e));
+            if mob.state != AiState::Idle {
+                mob.behavior.asleep = false; // woken up, and it stays that way
+            }
+
+            // where to go, if anywhere
+            let mut step = None;
+            let mut intent = Intent::Wait;
+            match (mob.state, spotted) {
+                (AiState::Idle, _) => {}
+                (AiState::Patrol, _) => intent = walk(&map, pt, facing, &has_player, &mut rng),
+                (AiState::Alert, Some(target)) => intent = Intent::Turn(toward(pt, target)),
+                (AiState::Alert, None) => {}
+                (AiState::Flee, _) => step = fields.from_players.downhill(&map, pt),
+                (AiState::Chase, Some(target)) => {
+                    let aim = toward(pt, target);
+                    let lined_up = pt.x == target.x || pt.y == target.y;
+                    if mob.behavior.shoots && lined_up && first_in_line(&map, pt, aim) == Some(target) {
+                        intent = Intent::Shoot(aim);
+                    } else if mob.behavior.hunts {
+                        // in sight, take the shared way towards the players
+                        step = fields.to_players.downhill(&map, pt);
+                    } else {
+                        intent = walk(&map, pt, aim, &has_player, &mut rng);
+                    }
+                }
+                (AiState::Chase, None) => {
+                    // walls hide the player, but a pusher remembers where they went, and shoves the wall over
+                    let goal = chases.get(e).map(|chase| chase.goal);
+                    match goal {
+                        Some(goal) if mob.behavior.pushes && can_push_at(&map, pt, goal) => {
+                            intent = Intent::Push(toward(pt, goal));
+                        }
+                        Some(goal) => {
+                            step = chases.get_mut(e).and_then(|chase| chase.follow(&map, pt));
+                            // no way around? then close in on the wall in between
+                            let ahead = pt.add(&toward(pt, goal).vector());
+                            if step.is_none() && mob.behavior.pushes && (pt.x == goal.x || pt.y == goal.y) && map.is_free_at(ahead) {
+                                step = Some(ahead);
+                            }
+                        }
+                        None => {}
+                    }
                 }
             }
+            if let Some(step) = step {
+                // someone in the way gets hit, or waited for
+                let d = toward(pt, step);
+                intent = if has_player(step) { Intent::Attack(d) } else { Intent::Move(d) };
+            }
+
+            let lost = mob.state == AiState::Chase && intent == Intent::Wait && spotted.is_none();
+            if lost || !matches!(mob.state, AiState::Alert | AiState::Chase) {
+                chases.remove(e); // there, no way to get there, or doing something else
+            }
+            intents.insert(e, intent).expect("unable to insert intent");
+            if let Some(rend) = rends.get_mut(e) {
+                rend.glyph = looks(mob, facing);
+            }
         }
+    }
+}
+
+/// walk on in this direction: hit a player in the way, or turn semi randomly at walls
+fn walk(map: &Map, from: Point, dir: Dir, has_player: impl Fn(Point) -> bool, rng: &mut RandomNumberGenerator) -> Intent {
+    let next = from.add(&dir.vector());
+    if has_player(next) {
+        return Intent::Attack(dir);
+    }
+    if map.is_free_at(next) {
+        return Intent::Move(dir);
+    }
 
-        // fix the looks
-        for (_mob, rend, dir) in (&mobs, &mut rends, &mut dirs).join() {
-            rend.glyph = match dir.dir {
-                Dir::Left  => rltk::to_cp437('<'),
-                Dir::Right => rltk::to_cp437('>'),
-                Dir::Up    => rltk::to_cp437('^'),
-                Dir::Down  => rltk::to_cp437('v'),
+    // change direction semi randomly
+    let mut left = dir.next();
+    let mut right = dir.prev();
+    if rng.range(0, 2) > 0 {
+        let temp = left;
+        left = right;
+        right = temp;
+    }
+
+    if map.is_free_at(from.add(&left.vector())) {
+        Intent::Turn(left)
+    } else if map.is_free_at(from.add(&right.vector())) {
+        Intent::Turn(right)
+    } else {
+        Intent::Turn(left)
+    }
+}
+
+/// what the monster does this turn, given what it saw.
+/// A hurt monster keeps fleeing for a while after it lost sight of players, instead of turning right back
+fn next_state(mob: &Monster, spotted: bool, hurt: bool, has_chase: bool) -> AiState {
+    let still_running = mob.state == AiState::Flee && mob.unseen < cons::FLEE_TURNS;
+    match mob.state {
+        _ if hurt && (spotted || still_running) => AiState::Flee,
+        AiState::Idle | AiState::Patrol if spotted => AiState::Alert,
+        AiState::Alert | AiState::Chase if spotted => AiState::Chase,
+        AiState::Alert | AiState::Chase if has_chase => AiState::Chase,
+        AiState::Idle | AiState::Patrol => mob.state,
+        _ => mob.resting(),
+    }
+}
+
+/// is the target in line, behind a wall right next to us? Only if the wall moves, or crushes whoever is behind it
+fn can_push_at(map: &Map, from: Point, target: Point) -> bool {
+    let dir = toward(from, target);
+    let wall = from.add(&dir.vector());
+    let behind = wall.add(&dir.vector());
+    let lined_up = from.x == target.x || from.y == target.y;
+    lined_up
+        && map.get_tile(wall.x, wall.y) == Some(Tile::Wall)
+        && (map.can_push(wall.x, wall.y, dir, cons::PUSH_STRENGTH) || map.is_occupied(behind.x, behind.y))
+}
+
+/// carry out the planned intents, in entity order, so a replay does the very same.
+/// An intent is kept to even when the player moved away: that is the point of showing them
+pub fn act_on_intents(ecs: &mut World) {
+    let mut shots: Vec<(Entity, Point, Dir)> = Vec::new();
+    let mut pushes: Vec<(Point, Dir)> = Vec::new();
+    {
+        let entities = ecs.entities();
+        let mut map = ecs.fetch_mut::<Map>();
+        let mut positions = ecs.write_storage::<Position>();
+        let mut dirs = ecs.write_storage::<Direction>();
+        let mut intents = ecs.write_storage::<Intent>();
+        let mut frozen = ecs.write_storage::<Frozen>();
+        let mut viewsheds = ecs.write_storage::<Viewshed>();
+        let mut wants_melee = ecs.write_storage::<WantsToMelee>();
+        let mobs = ecs.read_storage::<Monster>();
+        let mut rends = ecs.write_storage::<Renderable>();
+        let players = ecs.read_storage::<Player>();
+
+        let mut thawed: Vec<Entity> = Vec::new();
+        for (e, pos, dir, intent) in (&entities, &mut positions, &mut dirs, intents.drain()).join() {
+            let pt = pos.to_point();
+            let facing = dir.dir;
+
+            // hanging above the abyss, about to fall
+            if map.get_tile(pt.x, pt.y) == Some(Tile::Empty) { continue }
+
+            // frozen since the plan was made? sit this turn out
+            if let Some(ice) = frozen.get_mut(e) {
+                ice.turns -= 1;
+                if ice.turns <= 0 { thawed.push(e) }
+                continue;
+            }
+
+            match intent {
+                Intent::Wait => {}
+                Intent::Turn(d) => dir.dir = d,
+                Intent::Move(d) => {
+                    dir.dir = d;
+                    let next = pt.add(&d.vector());
+                    if map.is_free_at(next) {
+                        map.remove_occupant(pos.x, pos.y, e);
+                        pos.x = next.x;
+                        pos.y = next.y;
+                        map.add_occupant(pos.x, pos.y, e);
+                    }
+                }
+                Intent::Attack(d) => {
+                    dir.dir = d;
+                    let next = pt.add(&d.vector());
+                    let victim = map.get_occupants_at(next).iter().find(|o| players.contains(**o)).copied();
+                    if let Some(victim) = victim {
+                        wants_melee.insert(e, WantsToMelee { target: victim }).expect("unable to insert attack");
+                    }
+                }
+                Intent::Shoot(d) => {
+                    dir.dir = d;
+                    shots.push((e, pt, d));
+                }
+                Intent::Push(d) => {
+                    dir.dir = d;
+                    pushes.push((pt.add(&d.vector()), d));
+                }
+            }
+
+            // moved or turned? look again
+            if pos.to_point() != pt || dir.dir != facing {
+                if let Some(viewshed) = viewsheds.get_mut(e) {
+                    viewshed.dirty = true;
+                }
+            }
+            if let (Some(mob), Some(rend)) = (mobs.get(e), rends.get_mut(e)) {
+                rend.glyph = looks(mob, dir.dir);
             }
         }
+
+        for e in thawed {
+            frozen.remove(e);
+        }
+    }
+
+    for (shooter, from, dir) in shots {
+        spawn_projectile(ecs, from, dir, ProjectileKind::Shot, false, rltk::RED, Some(shooter));
+    }
+    for (wall, dir) in pushes {
+        let _res = apply_push(ecs, wall.x, wall.y, dir, cons::PUSH_STRENGTH);
+    }
+}
+
+/// sleeping, surprised, or heading somewhere
+fn looks(mob: &Monster, dir: Dir) -> rltk::FontCharType {
+    match (mob.state, dir) {
+        (AiState::Idle, _) if mob.behavior.asleep => rltk::to_cp437('z'),
+        (AiState::Alert, _) => rltk::to_cp437('!'),
+        (_, Dir::Left)  => rltk::to_cp437('<'),
+        (_, Dir::Right) => rltk::to_cp437('>'),
+        (_, Dir::Up)    => rltk::to_cp437('^'),
+        (_, Dir::Down)  => rltk::to_cp437('v'),
+    }
+}
+
+/// the direction that gets you closest to `to` in a single step
+fn toward(from: Point, to: Point) -> Dir {
+    let delta = to.sub(&from);
+    if delta.x.abs() >= delta.y.abs() {
+        if delta.x > 0 { Dir::Right } else { Dir::Left }
+    } else if delta.y > 0 {
+        Dir::Down
+    } else {
+        Dir::Up
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{components::Behavior, headless::Simulation, levels::Level, systems::{spawn_monster, Action}};
+
+    fn mob(state: AiState, behavior: Behavior) -> Monster {
+        Monster { behavior, state, unseen: 0 }
+    }
+
+    fn patroller() -> Behavior {
+        Behavior { patrols: true, hunts: true, flees_at: 1, ..Default::default() }
+    }
+
+    #[test]
+    fn spotting_alerts_then_chases() {
+        let m = mob(AiState::Patrol, patroller());
+        assert_eq!(next_state(&m, true, false, false), AiState::Alert);
+        let m = mob(AiState::Alert, patroller());
+        assert_eq!(next_state(&m, true, false, true), AiState::Chase);
+        assert_eq!(next_state(&m, false, false, false), AiState::Patrol, "nothing to chase, back to patrolling");
+    }
+
+    #[test]
+    fn a_chase_goes_on_out_of_sight() {
+        let m = mob(AiState::Chase, patroller());
+        assert_eq!(next_state(&m, false, false, true), AiState::Chase);
+        assert_eq!(next_state(&m, false, false, false), AiState::Patrol);
+    }
+
+    #[test]
+    fn resting_is_idle_or_patrol() {
+        let m = mob(AiState::Idle, patroller());
+        assert_eq!(next_state(&m, false, false, false), AiState::Idle);
+        let m = mob(AiState::Patrol, patroller());
+        assert_eq!(next_state(&m, false, false, false), AiState::Patrol);
+    }
+
+    #[test]
+    fn hurt_and_spotted_flees() {
+        for state in [AiState::Idle, AiState::Patrol, AiState::Alert, AiState::Chase] {
+            assert_eq!(next_state(&mob(state, patroller()), true, true, false), AiState::Flee);
+        }
+        assert_eq!(next_state(&mob(AiState::Patrol, patroller()), false, true, false), AiState::Patrol, "nothing to flee from");
+    }
+
+    #[test]
+    fn fleeing_lasts_until_out_of_sight_for_a_while() {
+        let mut m = mob(AiState::Flee, patroller());
+        for unseen in 1..cons::FLEE_TURNS {
+            m.unseen = unseen;
+            assert_eq!(next_state(&m, false, true, false), AiState::Flee, "{} turns out of sight", unseen);
+        }
+        m.unseen = cons::FLEE_TURNS;
+        assert_eq!(next_state(&m, false, true, false), AiState::Patrol);
+        m.unseen = 1;
+        assert_eq!(next_state(&m, false, false, false), AiState::Patrol, "healed, no need to run");
+    }
+
+    #[test]
+    fn a_woken_sleeper_stays_awake() {
+        let level = Level::parse("hall", "#######\n#@....#\n#######\n").unwrap();
+        let mut sim = Simulation::with_level(1, &level);
+        spawn_monster(&mut sim.state.ecs, 4, 1, "sleeper", Dir::Left);
+        let sleeper = |sim: &Simulation| sim.state.ecs.read_storage::<Monster>().join().next().unwrap().clone();
+        assert!(sleeper(&sim).behavior.asleep);
+
+        sim.step(Action::Move(Dir::Up)); // bump into the wall, in plain sight
+        let m = sleeper(&sim);
+        assert_ne!(m.state, AiState::Idle);
+        assert!(!m.behavior.asleep);
+        assert_eq!(m.resting(), AiState::Idle, "rests with its eyes open from now on");
     }
-}
\ No newline at end of file
+}
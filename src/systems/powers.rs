@@ -0,0 +1,165 @@
+use specs::prelude::*;
+
+use crate::{components::{Position, Player, Active, Direction, Monster, Power, Powers, Frozen, Renderable, Solid, CombatStats, ProjectileKind, Viewshed, LightSource}, map::{Map, PushResult}, geo::Point, util::Dir, systems::{apply_push, get_player, try_player_shoot}, cons};
+
+/// use a power in the direction the player is facing. Nothing happens if the player does not hold it
+pub fn use_power(power: Power, ecs: &mut World) {
+    let (player, pos, dir) = match get_player(ecs) {
+        Some(player) => player,
+        None => return,
+    };
+    let holds = ecs.read_storage::<Powers>().get(player).is_some_and(|p| p.list.contains(&power));
+    if !holds { return }
+
+    match power {
+        Power::Dash => dash(ecs, player, pos, dir),
+        Power::Pull => pull(ecs, pos, dir),
+        Power::Swap => swap(ecs, player, pos, dir),
+        Power::Freeze => freeze(ecs, pos, dir),
+        Power::Clone => clone(ecs, player, pos, dir),
+        Power::Bounce => try_player_shoot(ecs, ProjectileKind::Bouncing { bounces: 0 }),
+    }
+}
+
+/// hand control to the next body, in entity order
+pub fn switch_body(ecs: &mut World) {
+    let entities = ecs.entities();
+    let players = ecs.read_storage::<Player>();
+    let mut actives = ecs.write_storage::<Active>();
+
+    let bodies: Vec<Entity> = (&entities, &players).join().map(|(e, _)| e).collect();
+    let current = bodies.iter().position(|e| actives.contains(*e)).unwrap_or(0);
+    if let Some(next) = bodies.get((current + 1) % bodies.len().max(1)) {
+        actives.clear();
+        actives.insert(*next, Active {}).expect("unable to switch bodies");
+    }
+}
+
+/// the first wall or entity in line, looking from `from` towards `dir`
+pub fn first_in_line(map: &Map, from: Point, dir: Dir) -> Option<Point> {
+    let step = dir.vector();
+    let mut pt = from;
+    for _ in 0..cons::POWER_RANGE {
+        pt = pt.add(&step);
+        map.get_tile(pt.x, pt.y)?; // off the map
+        if map.is_pushable(pt.x, pt.y) { return Some(pt) }
+    }
+    None
+}
+
+fn dash(ecs: &mut World, player: Entity, pos: Point, dir: Dir) {
+    let mut map = ecs.fetch_mut::<Map>();
+    let mut positions = ecs.write_storage::<Position>();
+
+    let step = dir.vector();
+    let mut to = pos;
+    for _ in 0..cons::DASH_DISTANCE {
+        let next = to.add(&step);
+        if !map.is_free_at(next) { break }
+        to = next;
+    }
+
+    if let Some(p) = positions.get_mut(player) {
+        map.remove_occupant(pos.x, pos.y, player);
+        p.x = to.x;
+        p.y = to.y;
+        map.add_occupant(to.x, to.y, player);
+    }
+    if let Some(viewshed) = ecs.write_storage::<Viewshed>().get_mut(player) {
+        viewshed.dirty = true;
+    }
+}
+
+fn pull(ecs: &mut World, pos: Point, dir: Dir) {
+    let target = match first_in_line(&ecs.fetch::<Map>(), pos, dir) {
+        Some(target) => target,
+        None => return,
+    };
+
+    // drag it one cell at a time, until it is right in front of us or gets stuck
+    let step = dir.opposite().vector();
+    let mut at = target;
+    while at.x.abs_diff(pos.x) + at.y.abs_diff(pos.y) > 1 {
+        match apply_push(ecs, at.x, at.y, dir.opposite(), 1) {
+            PushResult::Pushed(_) => at = at.add(&step),
+            _ => break,
+        }
+    }
+}
+
+fn swap(ecs: &mut World, player: Entity, pos: Point, dir: Dir) {
+    let mut map = ecs.fetch_mut::<Map>();
+    let mut positions = ecs.write_storage::<Position>();
+
+    let target = match first_in_line(&map, pos, dir) {
+        Some(target) => target,
+        None => return,
+    };
+    let other = match map.get_occupants_at(target).first() {
+        Some(other) => *other,
+        None => return, // walls stay where they are
+    };
+
+    map.remove_occupant(pos.x, pos.y, player);
+    map.remove_occupant(target.x, target.y, other);
+    map.add_occupant(target.x, target.y, player);
+    map.add_occupant(pos.x, pos.y, other);
+    if let Some(p) = positions.get_mut(player) {
+        p.x = target.x;
+        p.y = target.y;
+    }
+    if let Some(p) = positions.get_mut(other) {
+        p.x = pos.x;
+        p.y = pos.y;
+    }
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+    for e in [player, other] {
+        if let Some(viewshed) = viewsheds.get_mut(e) {
+            viewshed.dirty = true;
+        }
+    }
+}
+
+fn clone(ecs: &mut World, player: Entity, pos: Point, dir: Dir) {
+    let bodies = ecs.read_storage::<Player>().join().count();
+    if bodies >= cons::MAX_BODIES { return }
+
+    let to = pos.add(&dir.vector());
+    if !ecs.fetch::<Map>().is_free_at(to) { return }
+
+    // a copy of the body, as it is right now
+    let renderable = ecs.read_storage::<Renderable>().get(player).cloned();
+    let stats = ecs.read_storage::<CombatStats>().get(player).cloned();
+    let powers = ecs.read_storage::<Powers>().get(player).cloned();
+    let light = ecs.read_storage::<LightSource>().get(player).cloned();
+    let viewshed = ecs.read_storage::<Viewshed>().get(player).map(|v| Viewshed::new(v.kind, v.radius, v.arc));
+    let mut builder = ecs
+        .create_entity()
+        .with(Position::new(to.x, to.y))
+        .with(Direction { dir })
+        .with(Player {})
+        .with(Solid {});
+    if let Some(c) = renderable { builder = builder.with(c); }
+    if let Some(c) = stats      { builder = builder.with(c); }
+    if let Some(c) = powers     { builder = builder.with(c); }
+    if let Some(c) = viewshed   { builder = builder.with(c); }
+    if let Some(c) = light      { builder = builder.with(c); }
+    let body = builder.build();
+
+    ecs.fetch_mut::<Map>().add_occupant(to.x, to.y, body);
+}
+
+fn freeze(ecs: &mut World, pos: Point, dir: Dir) {
+    let map = ecs.fetch::<Map>();
+    let monsters = ecs.read_storage::<Monster>();
+    let mut frozen = ecs.write_storage::<Frozen>();
+
+    let target = match first_in_line(&map, pos, dir) {
+        Some(target) => target,
+        None => return,
+    };
+    let monster = map.get_occupants_at(target).iter().find(|o| monsters.contains(**o)).copied();
+    if let Some(monster) = monster {
+        frozen.insert(monster, Frozen { turns: cons::FREEZE_TURNS }).expect("unable to freeze");
+    }
+}
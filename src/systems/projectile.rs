@@ -1,19 +1,22 @@
-use crate::{MyState, components::{Position, Projectile}, map::{Map, Tile}};
+use crate::{MyState, components::{Position, Projectile, ProjectileKind, CombatStats, SufferDamage}, map::{Map, Tile}, util::Dir, systems::apply_push};
 use specs::Entity;
 use specs::prelude::*;
 
 pub fn projectile_system(state: &mut MyState) {
 
     let mut removed : Vec<Entity> = Vec::new();
-    
+    let mut hits : Vec<(Entity, i32)> = Vec::new();
+    let mut pushes : Vec<(i32, i32, Dir, usize)> = Vec::new();
+
     // I live to please the borrow checker
     {
         let entities = state.ecs.entities();
         let mut positions = state.ecs.write_storage::<Position>();
         let mut projectiles = state.ecs.write_storage::<Projectile>();
-        let mut map = state.ecs.fetch_mut::<Map>();
+        let stats = state.ecs.read_storage::<CombatStats>();
+        let map = state.ecs.fetch::<Map>();
 
-        for (e, mut pos, mut proj) in (&entities, &mut positions, &mut projectiles).join() {
+        for (e, pos, proj) in (&entities, &mut positions, &mut projectiles).join() {
             proj.lifetime -= 1;
             if proj.lifetime < 0 {
                 removed.push(e);
@@ -21,20 +24,108 @@ pub fn projectile_system(state: &mut MyState) {
             }
             let (dx, dy) = proj.dir.xy();
             let (nx, ny) = (pos.x + dx, pos.y + dy);
-            
+
+            // flying into something that can be hurt? hit it, and stop here. The shooter is just flown over
+            let others: Vec<Entity> = map.get_occupants(nx, ny).iter().copied().filter(|o| Some(*o) != proj.owner).collect();
+            let target = others.iter().find(|o| stats.contains(**o)).copied();
+            if let Some(target) = target {
+                hits.push((target, proj.power));
+                if proj.knockback {
+                    pushes.push((nx, ny, proj.dir, proj.strength));
+                }
+                removed.push(e);
+                continue;
+            }
+
             let next_tile = map.get_tile(nx, ny).unwrap_or(Tile::Wall);
-            let next_tile_free = next_tile == Tile::Empty || next_tile == Tile::Floor; 
+            let next_tile_free = (next_tile == Tile::Empty || next_tile == Tile::Floor) && others.is_empty();
             if next_tile_free {
                 pos.x += dx;
                 pos.y += dy;
-            } else {
-                let _res = map.apply_push_effect(nx, ny, proj.dir);
-                removed.push(e);
+                continue;
+            }
+            match proj.kind {
+                ProjectileKind::Shot => {
+                    pushes.push((nx, ny, proj.dir, proj.strength));
+                    removed.push(e);
+                }
+                ProjectileKind::Bouncing { bounces } => {
+                    // the first wall only sends it back. after that, push whatever can be pushed
+                    if bounces > 0 && map.can_push(nx, ny, proj.dir, proj.strength) {
+                        pushes.push((nx, ny, proj.dir, proj.strength));
+                        removed.push(e);
+                    } else {
+                        proj.dir = bounce(&map, pos, proj.dir);
+                        proj.kind = ProjectileKind::Bouncing { bounces: bounces + 1 };
+                    }
+                }
             }
         }
     }
 
+    // resolve the hits. knocked into the abyss means falling, which the damage system handles
+    {
+        let mut damages = state.ecs.write_storage::<SufferDamage>();
+        for (target, power) in hits {
+            SufferDamage::new_damage(&mut damages, target, power);
+        }
+    }
+    for (x, y, dir, strength) in pushes {
+        let _res = apply_push(&mut state.ecs, x, y, dir, strength);
+    }
+
     for r in removed {
         state.ecs.delete_entity(r).expect("could not delete entity...");
     }
-}
\ No newline at end of file
+}
+
+/// the way out after hitting a wall: around the corner if there is only one way to go, straight back otherwise
+fn bounce(map: &Map, pos: &Position, dir: Dir) -> Dir {
+    let open = |d: Dir| {
+        let (dx, dy) = d.xy();
+        !matches!(map.get_tile(pos.x + dx, pos.y + dy), None | Some(Tile::Wall))
+    };
+    match (open(dir.next()), open(dir.prev())) {
+        (true, false) => dir.next(),
+        (false, true) => dir.prev(),
+        _ => dir.opposite(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{components::Active, geo::Point, headless::Simulation, levels::Level, systems::{spawn_projectile, Action}};
+
+    const CORRIDOR: &str = "\
+######
+#@...#
+######
+";
+
+    /// the player's hp after a bouncing shot went back and forth along the corridor
+    fn hp_after_a_bounce(owned: bool) -> i32 {
+        let mut sim = Simulation::with_level(1, &Level::parse("corridor", CORRIDOR).unwrap());
+        let player = {
+            let entities = sim.state.ecs.entities();
+            let actives = sim.state.ecs.read_storage::<Active>();
+            (&entities, &actives).join().next().unwrap().0
+        };
+        let owner = if owned { Some(player) } else { None };
+        spawn_projectile(&mut sim.state.ecs, Point::new(1, 1), Dir::Right, ProjectileKind::Bouncing { bounces: 0 }, false, rltk::BLUE2, owner);
+        for _ in 0..6 {
+            sim.step(Action::Move(Dir::Up)); // into the wall, so just wait
+        }
+        let stats = sim.state.ecs.read_storage::<CombatStats>();
+        stats.get(player).unwrap().hp
+    }
+
+    #[test]
+    fn a_bounced_shot_flies_over_its_shooter() {
+        let full = hp_after_a_bounce(true);
+        assert!(hp_after_a_bounce(false) < full, "without an owner the shot comes back and hits");
+        let sim = Simulation::with_level(1, &Level::parse("corridor", CORRIDOR).unwrap());
+        let stats = sim.state.ecs.read_storage::<CombatStats>();
+        assert_eq!(stats.join().next().unwrap().hp, full);
+    }
+}
@@ -0,0 +1,45 @@
+use specs::prelude::*;
+
+use crate::{components::{Position, SufferDamage, Viewshed}, map::{Map, PushResult, PushedKind}, util::Dir, cons};
+
+/// push the row of things at x, y in this direction, and let the world follow:
+/// moved entities get their new position, crushed ones get hurt
+pub fn apply_push(ecs: &mut World, x: i32, y: i32, dir: Dir, strength: usize) -> PushResult {
+    
+    let mut map = ecs.fetch_mut::<Map>();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut damages = ecs.write_storage::<SufferDamage>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+
+    let result = map.apply_push_effect(x, y, dir, strength);
+    match &result {
+        PushResult::Pushed(moved) | PushResult::Tumble(moved) => {
+            for object in moved {
+                match object.kind {
+                    PushedKind::Entity(e) => {
+                        if let Some(pos) = positions.get_mut(e) {
+                            pos.x = object.to.x;
+                            pos.y = object.to.y;
+                        }
+                        if let Some(viewshed) = viewsheds.get_mut(e) {
+                            viewshed.dirty = true;
+                        }
+                    }
+                    // moving walls changes the view of everyone
+                    PushedKind::Wall => {
+                        for viewshed in (&mut viewsheds).join() {
+                            viewshed.dirty = true;
+                        }
+                    }
+                }
+            }
+        }
+        PushResult::Crushed(victims) => {
+            for e in victims {
+                SufferDamage::new_damage(&mut damages, *e, cons::CRUSH_DAMAGE);
+            }
+        }
+        _ => {}
+    }
+    result
+}
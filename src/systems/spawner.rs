@@ -1,35 +1,56 @@
-use crate::{map::Map, state::MyState, components::{Position, Renderable, Monster, Direction, Solid, CombatStats}, cons, util::Dir};
-use rltk::RGB;
+use crate::{map::Map, state::MyState, raws::{Raws, spawn_template}, components::{Direction, Player, Active, Exit, Powers}, util::Dir};
+use rltk::RandomNumberGenerator;
 use specs::prelude::*;
 
+/// spawn monsters using the world's rng resource, so placement follows the seed.
+/// Every kind of monster in the templates is equally likely
 pub fn spawn_monsters(state: &mut MyState, map: &Map, count: u32) {
 
-    let mut rng = rltk::RandomNumberGenerator::new();
-    for _ in 0..count {
-        let x = ((rng.range(0, map.width / 2) * 2) + 1) as i32;
-        let y = ((rng.range(0, map.height / 2) * 2) + 1) as i32;
-
-        let glyph : rltk::FontCharType;
-        let roll = rng.roll_dice(1, 2);
-        match roll {
-            1 => { glyph = rltk::to_cp437('X') }
-            _ => { glyph = rltk::to_cp437('X') }
+    // roll everything first, the rng resource cannot be borrowed while we create entities
+    let kinds = state.ecs.fetch::<Raws>().monsters();
+    if kinds.is_empty() { return }
+    let mut rolls: Vec<(i32, i32, &str, Dir)> = Vec::new();
+    {
+        let mut rng = state.ecs.fetch_mut::<RandomNumberGenerator>();
+        for _ in 0..count {
+            let x = ((rng.range(0, map.width / 2) * 2) + 1) as i32;
+            let y = ((rng.range(0, map.height / 2) * 2) + 1) as i32;
+            let kind = &kinds[rng.range(0, kinds.len())];
+            rolls.push((x, y, kind, rng.rand()));
         }
+    }
+
+    for (x, y, kind, dir) in rolls {
+        spawn_monster(&mut state.ecs, x, y, kind, dir);
+    }
+}
 
-        state.ecs.create_entity()
-            .with(Position{ x, y })
-            .with(Renderable{
-                glyph,
-                foreground: RGB::named(rltk::RED),
-                background: cons::RGB_BACKGROUND,
-            })
-            // .with(Viewshed{ visible_tiles : Vec::new(), range: 8, dirty: true })
-            .with(Monster{})
-            .with(Direction{ dir: rng.rand() })
-            .with(Solid {})
-            .build();
+pub fn spawn_monster(ecs: &mut World, x: i32, y: i32, kind: &str, dir: Dir) {
+    match spawn_template(ecs, kind, x, y) {
+        Ok(builder) => { builder.with(Direction{ dir }).build(); }
+        Err(err) => rltk::console::log(&format!("could not spawn a monster: {}", err)),
     }
+}
+
+pub fn spawn_exit(ecs: &mut World, x: i32, y: i32) {
+    spawn_template(ecs, "exit", x, y)
+        .expect("the exit template is required")
+        .with(Exit {})
+        .build();
+}
+
+pub fn spawn_torch(ecs: &mut World, x: i32, y: i32) {
+    spawn_template(ecs, "torch", x, y)
+        .expect("the torch template is required")
+        .build();
+}
 
-        
-    
+pub fn make_player(ecs: &mut World, x: i32, y: i32) {
+    spawn_template(ecs, "player", x, y)
+        .expect("the player template is required")
+        .with(Player {})
+        .with(Active {})
+        .with(Direction { dir: Dir::Down})
+        .with(Powers::all())
+        .build();
 }
@@ -0,0 +1,48 @@
+use specs::prelude::*;
+
+use crate::{components::{Position, Direction, Viewshed, ViewShedKind, Active}, map::Map, geo::{Circle, Point}, util::Dir};
+
+/// recompute the dirty viewsheds, and show the player what their active body sees
+pub struct VisibilitySystem {}
+
+impl<'a> System<'a> for VisibilitySystem {
+    type SystemData = ( WriteExpect<'a, Map>,
+                        WriteStorage<'a, Viewshed>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Direction>,
+                        ReadStorage<'a, Active>
+                    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (mut map, mut viewsheds, positions, directions, actives) = data;
+
+        for (viewshed, pos, dir) in (&mut viewsheds, &positions, directions.maybe()).join() {
+            if !viewshed.dirty { continue }
+            viewshed.dirty = false;
+            let dir = dir.map_or(Dir::Down, |d| d.dir);
+            viewshed.visible = visible_cells(&map, pos.to_point(), dir, viewshed);
+        }
+
+        let seen: Vec<usize> = (&viewsheds, &actives).join()
+            .flat_map(|(viewshed, _)| viewshed.visible.iter().copied())
+            .collect();
+        map.set_visible(&seen);
+    }
+}
+
+/// every cell within the shape of the view that can be seen from the origin
+fn visible_cells(map: &Map, origin: Point, dir: Dir, viewshed: &Viewshed) -> Vec<usize> {
+    let circle = Circle::new(origin, viewshed.radius);
+    let half = viewshed.arc / 2.0;
+    let in_view = |p: &Point| match viewshed.kind {
+        ViewShedKind::Radial => circle.contains(p),
+        ViewShedKind::Cone => circle.contains(p) && circle.in_arc(p, dir.rad() - half, dir.rad() + half),
+        ViewShedKind::Line => {
+            let d = p.sub(&origin);
+            let v = dir.vector();
+            circle.contains(p) && d.x * v.y == d.y * v.x && d.x * v.x + d.y * v.y >= 0
+        }
+    };
+
+    map.cells_in_sight(origin, viewshed.radius.ceil() as i32, in_view)
+}
@@ -8,9 +8,10 @@ use rand::{
     distributions::{Distribution, Standard},
     Rng,
 };
+use serde::{Serialize, Deserialize};
 use crate::{cons, geo::Point}; // 0.8.0
 
-#[derive(Component, Debug, Copy, Clone, PartialEq)]
+#[derive(Component, Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Dir {
     Left,
     Right,
@@ -30,6 +31,25 @@ impl Dir {
         }
     }
 
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dir::Left => "left",
+            Dir::Down => "down",
+            Dir::Right => "right",
+            Dir::Up => "up",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Dir> {
+        match name {
+            "left" => Some(Dir::Left),
+            "down" => Some(Dir::Down),
+            "right" => Some(Dir::Right),
+            "up" => Some(Dir::Up),
+            _ => None,
+        }
+    }
+
     pub fn next(&self) -> Dir {
         match self {
             Dir::Left => Dir::Down,
@@ -48,6 +68,15 @@ impl Dir {
         }    
     }
 
+    pub fn opposite(&self) -> Dir {
+        match self {
+            Dir::Left => Dir::Right,
+            Dir::Down => Dir::Up,
+            Dir::Right => Dir::Left,
+            Dir::Up => Dir::Down,
+        }
+    }
+
     pub fn xy(&self) -> (i32, i32) {
         match self {
             Dir::Left =>  (-1, 0),